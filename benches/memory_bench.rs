@@ -0,0 +1,60 @@
+//! Peak heap usage of buffered (`process_lines`, then write) versus streamed
+//! (`process_lines_to_writer`) output. Run with `cargo bench --bench memory_bench`.
+
+use monadic_pipeline::output::write_lines;
+use monadic_pipeline::{process_lines, process_lines_to_writer, OutputOptions, ValidationConfig};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Forwards to `System`, tracking live and peak allocated bytes.
+struct PeakAlloc;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+// SAFETY: every call is forwarded unchanged to `System`; only counters are updated.
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+/// Peak bytes allocated while `run` executes, above what was live when it started.
+fn peak_during(run: impl FnOnce()) -> usize {
+    let base = LIVE.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    run();
+    PEAK.load(Ordering::Relaxed) - base
+}
+
+fn main() {
+    let cfg = ValidationConfig::default();
+    let opts = OutputOptions::default();
+    for count in [10_000, 100_000] {
+        let inputs = || (0..count).map(|i| format!("User{i},30,user{i}@example.com"));
+
+        let buffered = peak_during(|| {
+            let lines = process_lines(inputs(), &cfg).expect("benchmark should not fail");
+            write_lines(io::sink(), &lines, &opts).expect("sink never fails");
+        });
+        let streamed = peak_during(|| {
+            process_lines_to_writer(inputs(), &cfg, io::sink(), &opts)
+                .expect("benchmark should not fail");
+        });
+        println!("{count} lines: buffered peak {buffered} bytes, streamed peak {streamed} bytes");
+    }
+}
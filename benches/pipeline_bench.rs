@@ -1,11 +1,15 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use monadic_pipeline::{process_lines, AgeGroupingMode, ValidationConfig};
+use monadic_pipeline::{
+    process_lines, process_lines_to_writer, AgeGroupingMode, OutputOptions, ValidationConfig,
+};
+use std::io;
 
 fn pipeline_benchmark(c: &mut Criterion) {
     let cfg = ValidationConfig {
         min_age: 18,
         strict_email: true,
         age_grouping: AgeGroupingMode::Default,
+        ..ValidationConfig::default()
     };
 
     let inputs: Vec<String> = (0..1_000)
@@ -19,6 +23,18 @@ fn pipeline_benchmark(c: &mut Criterion) {
             black_box(result);
         });
     });
+
+    // Streams into a sink, so no output `Vec` is allocated; compare against `process_lines`
+    // above, which holds every formatted line until the caller writes them.
+    c.bench_function("process_lines_to_writer", |b| {
+        b.iter(|| {
+            let lines = inputs.clone();
+            let written =
+                process_lines_to_writer(lines, &cfg, io::sink(), &OutputOptions::default())
+                    .expect("benchmark should not fail");
+            black_box(written);
+        });
+    });
 }
 
 criterion_group!(benches, pipeline_benchmark);
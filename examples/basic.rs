@@ -5,6 +5,7 @@ fn main() {
         min_age: 18,
         strict_email: true,
         age_grouping: AgeGroupingMode::Default,
+        ..ValidationConfig::default()
     };
 
     let line = "Alice,30,alice@example.com";
@@ -0,0 +1,28 @@
+use monadic_pipeline::{parse_line, validate_user, User, ValidationConfig};
+
+/// Validates records and passes them through unchanged, skipping enrichment entirely.
+fn main() {
+    let cfg = ValidationConfig {
+        min_age: 18,
+        strict_email: true,
+        ..ValidationConfig::default()
+    };
+
+    let lines = [
+        "Alice,30,alice@example.com",
+        "Tim,12,tim@example.com",
+        "Bob,45,bob@example.com",
+    ];
+
+    for line in lines {
+        match parse_line(line).and_then(|user| validate_user(user, &cfg)) {
+            Ok(User {
+                name,
+                age: Some(age),
+                email,
+            }) if age < 40 => println!("{name},{age},{email}"),
+            Ok(user) => println!("skipped {} (custom age filter)", user.name),
+            Err(err) => eprintln!("rejected `{line}`: {err}"),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use monadic_pipeline::pipeline::Pipeline;
+use monadic_pipeline::{PipelineError, ValidationConfig};
+
+/// Assembles the standard stages with a custom step between validation and enrichment.
+fn main() {
+    let cfg = ValidationConfig {
+        min_age: 18,
+        strict_email: true,
+        ..ValidationConfig::default()
+    };
+
+    let pipeline = Pipeline::parse(&cfg)
+        .validate(&cfg)
+        .then(|user| {
+            if user.email.ends_with("@example.net") {
+                return Err(PipelineError::InvalidConfig {
+                    reason: format!("{} uses a blocked domain", user.name),
+                });
+            }
+            Ok(user)
+        })
+        .enrich(&cfg)
+        .format(&cfg);
+
+    let lines = [
+        "Alice,30,alice@example.com",
+        "Tim,12,tim@example.com",
+        "Bob,45,bob@example.net",
+    ];
+
+    for line in lines {
+        match pipeline.execute(line) {
+            Ok(record) => println!("{record}"),
+            Err(err) => eprintln!("rejected `{line}`: {err}"),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::domain::PipelineError;
+use crate::pipeline::process_line;
+use crate::validation::ValidationConfig;
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Spawn a thread that runs each line received on `input` through [`process_line`] and sends
+/// the result to `output`, in order.
+///
+/// Lines are processed independently, so cross-line checks such as `duplicate_email_policy`
+/// do not apply. A bounded `output` channel provides backpressure: the worker blocks while it
+/// is full. The worker exits once `input` is closed and drained, or when `output` is dropped.
+///
+/// ```
+/// use monadic_pipeline::channel::spawn_worker;
+/// use monadic_pipeline::ValidationConfig;
+/// use std::sync::mpsc;
+///
+/// let (lines, input) = mpsc::channel();
+/// let (output, results) = mpsc::sync_channel(1);
+/// let worker = spawn_worker(ValidationConfig::default(), input, output);
+///
+/// lines.send("Alice,30,alice@example.com".to_string()).unwrap();
+/// drop(lines);
+/// assert_eq!(results.recv().unwrap().unwrap(), "Alice (30, 30s) -> username=alice");
+/// worker.join().unwrap();
+/// ```
+pub fn spawn_worker(
+    cfg: ValidationConfig,
+    input: Receiver<String>,
+    output: SyncSender<Result<String, PipelineError>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for line in input {
+            if output.send(process_line(&line, &cfg)).is_err() {
+                break;
+            }
+        }
+    })
+}
@@ -4,22 +4,40 @@ use std::fmt;
 
 /// Represents a parsed user prior to enrichment.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct User {
     pub name: String,
-    pub age: u8,
+    /// `None` when the age field was left empty.
+    pub age: Option<u8>,
     pub email: String,
 }
 
 /// Represents additional context derived from the raw user data.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct EnrichedUser {
     pub user: User,
     pub age_group: AgeGroup,
     pub username: String,
+    /// Up to three uppercase initials taken from the name tokens.
+    pub initials: String,
+    /// Name with internal whitespace normalised to single spaces.
+    pub display_name: String,
+    /// Email provider classification, populated only when enabled in the config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<EmailProvider>,
+    /// Stable hash of the normalised name, age and email for downstream deduplication,
+    /// populated only when `ValidationConfig::record_hash` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_hash: Option<String>,
+    /// Fields added by custom enrichers (see `enrichers::Enricher`).
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 /// Human friendly bucket describing a user's age segment.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct AgeGroup {
     label: String,
 }
@@ -42,6 +60,78 @@ impl fmt::Display for AgeGroup {
     }
 }
 
+/// Display labels for the age group buckets, defaulting to English.
+///
+/// Missing fields keep their English default, so a locale file only needs the labels it
+/// translates. `AgeGroupingMode::FineGrained` ranges such as `25-29` are numeric and not
+/// covered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgeGroupLabels {
+    /// `AgeGroupingMode::Default`, ages 0-12.
+    pub under_teen: String,
+    /// `AgeGroupingMode::Default`, ages 13-19.
+    pub teens: String,
+    pub twenties: String,
+    pub thirties: String,
+    pub forties: String,
+    /// `AgeGroupingMode::Default`, ages 50 and over.
+    pub fifty_plus: String,
+    /// `AgeGroupingMode::Wide`, ages 0-17.
+    pub young: String,
+    /// `AgeGroupingMode::Wide`, ages 18-45.
+    pub adult: String,
+    /// `AgeGroupingMode::Wide`, ages 46 and over.
+    pub senior: String,
+    /// Users without an age, in every mode.
+    pub unknown: String,
+}
+
+impl Default for AgeGroupLabels {
+    fn default() -> Self {
+        Self {
+            under_teen: "<teen".into(),
+            teens: "teens".into(),
+            twenties: "20s".into(),
+            thirties: "30s".into(),
+            forties: "40s".into(),
+            fifty_plus: "50+".into(),
+            young: "young".into(),
+            adult: "adult".into(),
+            senior: "senior".into(),
+            unknown: "unknown".into(),
+        }
+    }
+}
+
+/// Coarse classification of the mailbox provider behind an email address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "kebab-case")]
+pub enum EmailProvider {
+    Gmail,
+    Outlook,
+    Corporate,
+    Other,
+}
+
+impl EmailProvider {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Gmail => "gmail",
+            Self::Outlook => "outlook",
+            Self::Corporate => "corporate",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for EmailProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
 /// Strategy used for deriving age groups.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -52,19 +142,229 @@ pub enum AgeGroupingMode {
     #[value(alias = "fine")]
     FineGrained,
     Wide,
+    /// The ranges in `ValidationConfig::age_buckets`.
+    Custom,
+}
+
+/// An inclusive age range and its label for `AgeGroupingMode::Custom`, e.g. `0,17,minor`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgeBucket {
+    pub min: u8,
+    pub max: u8,
+    pub label: String,
+}
+
+/// How `process_lines` reacts to an email address it has already seen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateEmailPolicy {
+    #[default]
+    Allow,
+    Warn,
+    Error,
+}
+
+/// How `validate_user` treats an age above the configured ceiling.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum AgeOverRangePolicy {
+    /// Reject the record with `PipelineError::AgeOutOfRange`.
+    #[default]
+    Error,
+    /// Lower the age to the ceiling, log a warning and keep the record.
+    Clamp,
+}
+
+/// How blank input lines are treated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmptyLinePolicy {
+    /// Readers drop blank lines silently.
+    #[default]
+    Skip,
+    /// Blank lines are kept and rejected as `PipelineError::Parse`.
+    Error,
+}
+
+/// How much of an email address is hidden before it is shown in logs, errors or output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmailMaskPolicy {
+    /// Show the address unchanged.
+    None,
+    /// Keep the first character of the local part: `u***@example.com`.
+    #[default]
+    Local,
+    /// Also mask the domain, keeping its first character and top-level domain: `u***@e***.com`.
+    LocalAndDomain,
+}
+
+/// How `EnrichedUser::username` is derived.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UsernameScheme {
+    /// Lowercase alphanumerics of the name (falling back to the email local part).
+    #[default]
+    Name,
+    /// First 8 hex digits of the SHA-256 of the normalised email: stable, and does not reveal
+    /// the name.
+    Hash,
+}
+
+/// Field used to order output records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Name,
+    Age,
+    Username,
+    #[value(alias = "age_group")]
+    AgeGroup,
+}
+
+/// Post-enrichment predicate on output records; records it does not match are dropped rather
+/// than rejected. Unset criteria match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordFilter {
+    /// Keep only records with at least this age; records without an age are dropped.
+    pub min_age: Option<u8>,
+    /// Keep only records whose email domain equals this one, ignoring case.
+    pub domain: Option<String>,
+}
+
+impl RecordFilter {
+    /// Whether `enriched` passes every configured criterion.
+    ///
+    /// ```
+    /// use monadic_pipeline::{enrich_user, parse_line, RecordFilter};
+    ///
+    /// let filter = RecordFilter { min_age: Some(30), domain: Some("Example.com".into()) };
+    /// let enriched = enrich_user(parse_line("Alice,30,alice@example.com").unwrap());
+    /// assert!(filter.matches(&enriched));
+    /// ```
+    pub fn matches(&self, enriched: &EnrichedUser) -> bool {
+        let age_ok = self
+            .min_age
+            .is_none_or(|min_age| enriched.user.age.is_some_and(|age| age >= min_age));
+        let domain_ok = self.domain.as_deref().is_none_or(|domain| {
+            enriched
+                .user
+                .email
+                .trim()
+                .rsplit_once('@')
+                .is_some_and(|(_, actual)| actual.eq_ignore_ascii_case(domain))
+        });
+        age_ok && domain_ok
+    }
+}
+
+/// Why an email address was rejected.
+#[derive(thiserror::Error, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailError {
+    #[error("address is empty")]
+    Empty,
+    #[error("missing `@`")]
+    MissingAt,
+    #[error("more than one `@`")]
+    MultipleAt,
+    #[error("nothing before `@`")]
+    EmptyLocal,
+    #[error("nothing after `@`")]
+    EmptyDomain,
+    #[error("domain has no `.`")]
+    MissingDomainDot,
+    #[error("domain has fewer than {min} labels")]
+    TooFewDomainLabels { min: u8 },
+    #[error("top-level domain is shorter than {min} characters")]
+    TldTooShort { min: u8 },
+    #[error("top-level domain is not in the known list")]
+    UnknownTld,
+    #[error("local part exceeds 64 characters")]
+    LocalTooLong,
+    #[error("contains characters not allowed in strict mode")]
+    RegexMismatch,
 }
 
 /// Errors produced during pipeline processing.
+///
+/// Serialises with the variant name in a `kind` field next to the variant's own fields, e.g.
+/// `{"kind":"InvalidAge","age":5,"min_age":18}`.
 #[derive(thiserror::Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
 pub enum PipelineError {
-    #[error("failed to parse line: {reason}")]
-    Parse { reason: String },
+    #[error("failed to parse line: {reason}{}", column_suffix(.column))]
+    Parse {
+        reason: String,
+        /// 1-based character column of the problem, when the parser can pinpoint it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        column: Option<usize>,
+    },
     #[error("name must not be empty")]
     EmptyName,
-    #[error("age {age} is below configured minimum {min_age}")]
+    #[error("age is required")]
+    MissingAge,
+    /// `age` is below `min_age`, or is 0 under `reject_zero_age` when `age >= min_age`.
+    #[error("{}", invalid_age_message(*.age, *.min_age))]
     InvalidAge { age: u8, min_age: u8 },
     #[error("age {age} exceeds supported upper bound")]
     AgeOutOfRange { age: u8 },
-    #[error("invalid email address: {email}")]
-    InvalidEmail { email: String },
+    #[error("invalid email address: {email} ({reason})")]
+    InvalidEmail { email: String, reason: EmailError },
+    #[error("{failed} of {total} lines were rejected, exceeding the maximum error rate")]
+    ErrorRateExceeded { failed: usize, total: usize },
+    #[error("duplicate email address: {email}")]
+    DuplicateEmail { email: String },
+    #[error("failed to read input: {reason}")]
+    Io { reason: String },
+    #[error("failed to write output: {reason}")]
+    Output { reason: String },
+    #[error("invalid configuration: {reason}")]
+    InvalidConfig { reason: String },
+}
+
+impl PipelineError {
+    /// A short, human-facing suggestion for resolving the error.
+    ///
+    /// Kept separate from `Display` so the error message itself stays machine-friendly.
+    pub fn hint(&self) -> Option<&'static str> {
+        let hint = match self {
+            Self::Parse { .. } => {
+                "check that the line has `name,age,email` fields and balanced double quotes"
+            }
+            Self::EmptyName => "fill in the name field or remove the record",
+            Self::MissingAge => "fill in the age or pass --optional-age",
+            Self::InvalidAge { age, min_age } if age >= min_age => {
+                "fix the record or disable reject_zero_age"
+            }
+            Self::InvalidAge { .. } => "lower --min-age or fix the record",
+            Self::AgeOutOfRange { .. } => "fix the record or pass --age-over-range clamp",
+            Self::InvalidEmail { .. } => "fix the address or remove the record",
+            Self::ErrorRateExceeded { .. } => {
+                "check that the input has the expected format, or raise --max-error-rate"
+            }
+            Self::DuplicateEmail { .. } => {
+                "remove the repeated record or pass --duplicate-emails warn"
+            }
+            Self::Io { .. } => "check that the input exists and is readable",
+            Self::Output { .. } => "check that the output destination is writable and has space",
+            Self::InvalidConfig { .. } => "check the command-line flags and configuration values",
+        };
+        Some(hint)
+    }
+}
+
+fn invalid_age_message(age: u8, min_age: u8) -> String {
+    if age < min_age {
+        format!("age {age} is below configured minimum {min_age}")
+    } else {
+        format!("age {age} is rejected as a likely placeholder (reject_zero_age)")
+    }
+}
+
+fn column_suffix(column: &Option<usize>) -> String {
+    column
+        .map(|column| format!(" at column {column}"))
+        .unwrap_or_default()
 }
@@ -0,0 +1,93 @@
+use crate::domain::{AgeBucket, AgeGroup, AgeGroupLabels, AgeGroupingMode, User, UsernameScheme};
+use crate::{compute_age_group, generate_username, hash_username};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Derived fields accumulated while the enrichment chain runs over a user.
+///
+/// Each enricher sees what the previous ones produced and may add to or replace it; fields a
+/// chain leaves unset end up empty in the `EnrichedUser`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnrichmentData {
+    pub age_group: Option<AgeGroup>,
+    pub username: Option<String>,
+    /// Custom fields, copied to `EnrichedUser::extra`.
+    pub extra: Map<String, Value>,
+}
+
+/// A single step of enrichment, deriving fields from a validated user.
+///
+/// The age group and username are built-in enrichers; custom enrichers registered on the
+/// config (see `ValidationConfigBuilder::enricher`) run after them, in registration order.
+///
+/// ```
+/// use monadic_pipeline::enrichers::{Enricher, EnrichmentData};
+/// use monadic_pipeline::{process_lines_enriched, User, ValidationConfig};
+///
+/// #[derive(Debug)]
+/// struct NameLength;
+///
+/// impl Enricher for NameLength {
+///     fn enrich(&self, user: &User, acc: &mut EnrichmentData) {
+///         acc.extra.insert("name_len".into(), user.name.chars().count().into());
+///     }
+/// }
+///
+/// let cfg = ValidationConfig::builder().enricher(NameLength).build().unwrap();
+/// let lines = vec!["Alice,30,alice@example.com".to_string()];
+/// let records = process_lines_enriched(lines, &cfg).unwrap();
+/// assert_eq!(records[0].extra["name_len"], 5);
+/// ```
+pub trait Enricher: fmt::Debug + Send + Sync {
+    fn enrich(&self, user: &User, acc: &mut EnrichmentData);
+}
+
+impl<E: Enricher + ?Sized> Enricher for Arc<E> {
+    fn enrich(&self, user: &User, acc: &mut EnrichmentData) {
+        (**self).enrich(user, acc)
+    }
+}
+
+/// Buckets the age with the given grouping, labels and label overrides.
+#[derive(Debug, Clone, Copy)]
+pub struct AgeGroupEnricher<'a> {
+    pub mode: AgeGroupingMode,
+    /// Bucket width used by `AgeGroupingMode::FineGrained`.
+    pub fine_grained_width: u8,
+    /// Ranges used by `AgeGroupingMode::Custom`.
+    pub buckets: &'a [AgeBucket],
+    pub labels: &'a AgeGroupLabels,
+    pub overrides: &'a HashMap<String, String>,
+}
+
+impl Enricher for AgeGroupEnricher<'_> {
+    fn enrich(&self, user: &User, acc: &mut EnrichmentData) {
+        acc.age_group = Some(compute_age_group(
+            user.age,
+            self.mode,
+            self.fine_grained_width,
+            self.buckets,
+            self.labels,
+            self.overrides,
+        ));
+    }
+}
+
+/// Derives the username with the given scheme.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsernameEnricher {
+    pub scheme: UsernameScheme,
+    /// Drop a `+tag` from the email's local part when it stands in for the name.
+    pub strip_plus_tags: bool,
+}
+
+impl Enricher for UsernameEnricher {
+    fn enrich(&self, user: &User, acc: &mut EnrichmentData) {
+        acc.username = Some(match self.scheme {
+            UsernameScheme::Name => generate_username(user, self.strip_plus_tags),
+            UsernameScheme::Hash => hash_username(&user.email),
+        });
+    }
+}
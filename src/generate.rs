@@ -0,0 +1,63 @@
+//! Deterministic synthetic input for tests and benchmarks.
+
+const FIRST_NAMES: &[&str] = &[
+    "Alice", "Bob", "Carol", "Dave", "Erin", "Frank", "Grace", "Heidi", "Ivan", "Judy", "Mallory",
+    "Niaj", "Olivia", "Peggy", "Rupert", "Sybil", "Trent", "Victor", "Walter", "Yuki",
+];
+const LAST_NAMES: &[&str] = &[
+    "Smith", "Jones", "Tanaka", "Garcia", "Müller", "Rossi", "Dubois", "Kim", "Silva", "Novak",
+];
+const DOMAINS: &[&str] = &[
+    "example.com",
+    "gmail.com",
+    "outlook.com",
+    "yahoo.co.jp",
+    "acme.io",
+    "corp.example.org",
+];
+const MIN_AGE: u64 = 5;
+const MAX_AGE: u64 = 90;
+
+/// SplitMix64: tiny, fast and fully determined by its seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[(self.next_u64() % items.len() as u64) as usize]
+    }
+}
+
+/// Generate `count` `name,age,email` rows; the same `seed` always yields the same rows.
+///
+/// Names, ages and domains vary, while emails stay unique by embedding the row index.
+///
+/// ```
+/// use monadic_pipeline::generate::synthetic_lines;
+///
+/// let rows = synthetic_lines(3, 42);
+/// assert_eq!(rows.len(), 3);
+/// assert_eq!(rows, synthetic_lines(3, 42));
+/// ```
+pub fn synthetic_lines(count: usize, seed: u64) -> Vec<String> {
+    let mut rng = SplitMix64(seed);
+    (0..count)
+        .map(|idx| {
+            let first = rng.pick(FIRST_NAMES);
+            let last = rng.pick(LAST_NAMES);
+            let age = MIN_AGE + rng.next_u64() % (MAX_AGE - MIN_AGE + 1);
+            let domain = rng.pick(DOMAINS);
+            format!(
+                "{first} {last},{age},{}.{idx}@{domain}",
+                first.to_lowercase()
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,317 @@
+use crate::domain::{EmptyLinePolicy, PipelineError};
+use crate::parsing::ParseOptions;
+use crate::pipeline::process_lines;
+use crate::validation::ValidationConfig;
+use std::io::{self, BufRead, Read};
+use tracing::debug;
+
+const UTF8_BOM: char = '\u{FEFF}';
+
+/// Collect the data lines from `reader`.
+///
+/// A leading UTF-8 byte-order mark is stripped and trailing whitespace is trimmed. Comment
+/// lines (see `ParseOptions::comment_char`) are dropped, as are empty lines unless
+/// `ParseOptions::empty_lines` is `EmptyLinePolicy::Error`.
+///
+/// A line longer than `ParseOptions::max_line_len` bytes fails the read with an
+/// `InvalidData` error wrapping a `PipelineError::Parse` that names the line, without
+/// buffering more of it than the limit:
+///
+/// ```
+/// use monadic_pipeline::{read_lines, ParseOptions};
+///
+/// let opts = ParseOptions { max_line_len: 8, ..ParseOptions::default() };
+/// let err = read_lines("a,1,a@b.c\nshort\n".as_bytes(), &opts).unwrap_err();
+/// assert_eq!(err.to_string(), "failed to parse line: line 1 is longer than 8 bytes");
+/// ```
+pub fn read_lines<R: BufRead>(reader: R, opts: &ParseOptions) -> io::Result<Vec<String>> {
+    stream_lines(reader, opts).collect()
+}
+
+/// Like [`read_lines`], keeping the 1-based number of each line in `reader`, so errors can
+/// name it even after comment and blank lines were dropped.
+///
+/// ```
+/// use monadic_pipeline::input::read_numbered_lines;
+/// use monadic_pipeline::ParseOptions;
+///
+/// let opts = ParseOptions { comment_char: Some('#'), ..ParseOptions::default() };
+/// let lines = read_numbered_lines("# note\nAlice,30,alice@example.com\n".as_bytes(), &opts);
+/// assert_eq!(lines.unwrap()[0].line_no, Some(2));
+/// ```
+pub fn read_numbered_lines<R: BufRead>(
+    reader: R,
+    opts: &ParseOptions,
+) -> io::Result<Vec<InputLine>> {
+    stream_numbered_lines(reader, opts).collect()
+}
+
+/// Like [`read_lines`], but yields each data line as soon as it has been read instead of
+/// waiting for the end of the input, e.g. to follow a growing stream. Nothing is yielded
+/// after an error.
+///
+/// ```
+/// use monadic_pipeline::input::stream_lines;
+/// use monadic_pipeline::ParseOptions;
+///
+/// let opts = ParseOptions::default();
+/// let mut lines = stream_lines("\u{FEFF}Alice,30,alice@example.com\n\n".as_bytes(), &opts);
+/// assert_eq!(lines.next().unwrap().unwrap(), "Alice,30,alice@example.com");
+/// assert!(lines.next().is_none());
+/// ```
+pub fn stream_lines<'a, R: BufRead + 'a>(
+    reader: R,
+    opts: &'a ParseOptions,
+) -> impl Iterator<Item = io::Result<String>> + 'a {
+    stream_numbered_lines(reader, opts).map(|line| line.map(|line| line.text))
+}
+
+/// [`stream_lines`] keeping line numbers, as in [`read_numbered_lines`].
+pub fn stream_numbered_lines<'a, R: BufRead + 'a>(
+    mut reader: R,
+    opts: &'a ParseOptions,
+) -> impl Iterator<Item = io::Result<InputLine>> + 'a {
+    let mut buf = Vec::new();
+    // Room for the longest allowed line plus a `\r\n` terminator.
+    let limit = opts.max_line_len.saturating_add(2) as u64;
+    let mut idx = 0;
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        while !failed {
+            buf.clear();
+            let line = match (&mut reader).take(limit).read_until(b'\n', &mut buf) {
+                Ok(0) => return None,
+                Ok(_) => data_line(&buf, idx, opts),
+                Err(err) => Err(err),
+            };
+            idx += 1;
+            match line {
+                Ok(Some(text)) => {
+                    return Some(Ok(InputLine {
+                        line_no: Some(idx),
+                        text,
+                    }))
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    })
+}
+
+/// Decode the raw line at 0-based `idx`, returning `None` for lines readers drop.
+fn data_line(raw: &[u8], idx: usize, opts: &ParseOptions) -> io::Result<Option<String>> {
+    let content = raw.strip_suffix(b"\n").unwrap_or(raw);
+    let content = content.strip_suffix(b"\r").unwrap_or(content);
+    if content.len() > opts.max_line_len {
+        let err = PipelineError::Parse {
+            reason: format!(
+                "line {} is longer than {} bytes",
+                idx + 1,
+                opts.max_line_len
+            ),
+            column: None,
+        };
+        return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+    }
+    let line = std::str::from_utf8(content).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream did not contain valid UTF-8",
+        )
+    })?;
+    let mut line = line.trim_end();
+    if idx == 0 {
+        if let Some(rest) = line.strip_prefix(UTF8_BOM) {
+            debug!("stripped UTF-8 byte-order mark from input");
+            line = rest;
+        }
+    }
+    let keep_empty = opts.empty_lines == EmptyLinePolicy::Error;
+    Ok(((keep_empty || !line.is_empty()) && !opts.is_comment(line)).then(|| line.to_owned()))
+}
+
+/// A data line as handed to the pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLine {
+    /// 1-based line number in the stream it was read from; `None` when unknown, e.g. for
+    /// lines passed in as plain `String`s.
+    pub line_no: Option<usize>,
+    pub text: String,
+}
+
+impl From<String> for InputLine {
+    fn from(text: String) -> Self {
+        Self {
+            line_no: None,
+            text,
+        }
+    }
+}
+
+/// Somewhere input lines come from: stdin, a file, a request body, ...
+///
+/// Implementations that wrap a byte stream should delegate to [`read_lines`] and
+/// [`read_numbered_lines`] so BOM, comment and blank-line handling and line numbers stay
+/// consistent.
+pub trait InputSource {
+    fn read_lines(&self, opts: &ParseOptions) -> anyhow::Result<Vec<String>>;
+
+    /// `read_lines` with line numbers (see [`read_numbered_lines`]). The default leaves them
+    /// unknown.
+    fn read_numbered_lines(&self, opts: &ParseOptions) -> anyhow::Result<Vec<InputLine>> {
+        Ok(self
+            .read_lines(opts)?
+            .into_iter()
+            .map(InputLine::from)
+            .collect())
+    }
+
+    /// Yield the numbered lines one at a time as they are read, so processing can start before
+    /// the source is exhausted. The default reads everything up front with
+    /// `read_numbered_lines`; stream-backed sources should override it with
+    /// [`stream_numbered_lines`]. Nothing is yielded after an error.
+    fn stream_numbered_lines<'a>(
+        &'a self,
+        opts: &'a ParseOptions,
+    ) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<InputLine>> + 'a>> {
+        Ok(Box::new(
+            self.read_numbered_lines(opts)?.into_iter().map(Ok),
+        ))
+    }
+
+    /// Read the source as independently failing parts (e.g. one per file of a directory), so
+    /// callers can skip an unreadable part and keep the rest. Single-stream sources return one
+    /// part holding `read_numbered_lines`.
+    fn read_parts(&self, opts: &ParseOptions) -> Vec<InputPart> {
+        vec![InputPart {
+            name: String::new(),
+            lines: self.read_numbered_lines(opts),
+        }]
+    }
+}
+
+/// Lines from one part of an `InputSource`.
+#[derive(Debug)]
+pub struct InputPart {
+    /// Display name such as a file path; empty when the source has none.
+    pub name: String,
+    /// The part's lines, or why it could not be read.
+    pub lines: anyhow::Result<Vec<InputLine>>,
+}
+
+/// In-memory `InputSource`, mainly for tests and embedding.
+///
+/// ```
+/// use monadic_pipeline::{InputSource, MemorySource, ParseOptions};
+///
+/// let source = MemorySource::new("Alice,30,alice@example.com\n\n");
+/// assert_eq!(source.read_lines(&ParseOptions::default()).unwrap().len(), 1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    text: String,
+}
+
+impl MemorySource {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+}
+
+impl InputSource for MemorySource {
+    fn read_lines(&self, opts: &ParseOptions) -> anyhow::Result<Vec<String>> {
+        Ok(read_lines(self.text.as_bytes(), opts)?)
+    }
+
+    fn read_numbered_lines(&self, opts: &ParseOptions) -> anyhow::Result<Vec<InputLine>> {
+        Ok(read_numbered_lines(self.text.as_bytes(), opts)?)
+    }
+}
+
+/// Several sources read one after another, concatenated in order.
+impl<S: InputSource> InputSource for [S] {
+    fn read_lines(&self, opts: &ParseOptions) -> anyhow::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        for source in self {
+            lines.extend(source.read_lines(opts)?);
+        }
+        Ok(lines)
+    }
+
+    fn read_numbered_lines(&self, opts: &ParseOptions) -> anyhow::Result<Vec<InputLine>> {
+        let mut lines = Vec::new();
+        for source in self {
+            lines.extend(source.read_numbered_lines(opts)?);
+        }
+        Ok(lines)
+    }
+
+    /// Opens each source only once the previous one is exhausted.
+    fn stream_numbered_lines<'a>(
+        &'a self,
+        opts: &'a ParseOptions,
+    ) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<InputLine>> + 'a>> {
+        let mut failed = false;
+        let lines = self
+            .iter()
+            .flat_map(move |source| {
+                let lines: Box<dyn Iterator<Item = _>> = match source.stream_numbered_lines(opts) {
+                    Ok(lines) => lines,
+                    Err(err) => Box::new(std::iter::once(Err(err))),
+                };
+                lines
+            })
+            .map_while(move |line| {
+                if failed {
+                    return None;
+                }
+                failed = line.is_err();
+                Some(line)
+            });
+        Ok(Box::new(lines))
+    }
+
+    fn read_parts(&self, opts: &ParseOptions) -> Vec<InputPart> {
+        self.iter()
+            .flat_map(|source| source.read_parts(opts))
+            .collect()
+    }
+}
+
+/// Run the full pipeline over every line of an in-memory buffer, file, or stream.
+///
+/// ```
+/// use monadic_pipeline::{process_reader, ValidationConfig};
+/// use std::io::Cursor;
+///
+/// let input = Cursor::new("Alice,30,alice@example.com\n\nBob,45,bob@example.com\n");
+/// let outputs = process_reader(input, &ValidationConfig::default()).unwrap();
+/// assert_eq!(outputs.len(), 2);
+/// ```
+pub fn process_reader<R: BufRead>(
+    reader: R,
+    cfg: &ValidationConfig,
+) -> Result<Vec<String>, PipelineError> {
+    let lines = read_numbered_lines(reader, &cfg.parse).map_err(read_error)?;
+    process_lines(lines, cfg)
+}
+
+/// The `PipelineError` a read failed with (e.g. `PipelineError::Parse` for an overlong line),
+/// or `PipelineError::Io` for a genuine I/O failure.
+fn read_error(err: io::Error) -> PipelineError {
+    if !err
+        .get_ref()
+        .is_some_and(|inner| inner.is::<PipelineError>())
+    {
+        return PipelineError::Io {
+            reason: err.to_string(),
+        };
+    }
+    let inner = err.into_inner().expect("checked above");
+    *inner.downcast::<PipelineError>().expect("checked above")
+}
@@ -1,49 +1,176 @@
+//! Monadic pipeline for CSV-like user records.
+//!
+//! Each record flows through four stages, composed with `Result` combinators:
+//!
+//! 1. [`parse_line`] / [`parse_line_with`] turn a raw line into a [`User`].
+//! 2. [`validate_user`] applies the [`ValidationConfig`] rules.
+//! 3. [`enrich_user`] / [`enrich_user_with_mode`] derive age group, username and initials.
+//! 4. [`format_user`] renders the [`EnrichedUser`] for output.
+//!
+//! [`process_line`] and [`process_lines`] run the whole chain. Advanced callers can stop after
+//! any stage, or interleave their own steps, by calling the stages directly:
+//!
+//! ```
+//! use monadic_pipeline::{enrich_user_with_mode, format_user, parse_line, validate_user};
+//! use monadic_pipeline::{AgeGroupingMode, ValidationConfig};
+//!
+//! let cfg = ValidationConfig::default();
+//! let output = parse_line("Alice,30,alice@example.com")
+//!     .and_then(|user| validate_user(user, &cfg))
+//!     .map(|user| enrich_user_with_mode(user, AgeGroupingMode::Wide))
+//!     .map(|enriched| format_user(&enriched))
+//!     .unwrap();
+//! assert_eq!(output, "Alice (30, adult) -> username=alice");
+//! ```
+
 #![deny(unsafe_code)]
 
+#[cfg(feature = "channel")]
+pub mod channel;
 pub mod domain;
+pub mod enrichers;
+pub mod generate;
+pub mod input;
 pub mod logging;
+pub mod output;
+pub mod parsing;
 pub mod pipeline;
+pub mod rules;
+pub mod run;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod stats;
+pub mod template;
 pub mod validation;
 
-pub use crate::domain::{AgeGroup, AgeGroupingMode, EnrichedUser, PipelineError, User};
-pub use crate::logging::{init_logging, LoggingMode};
-pub use crate::pipeline::{process_line, process_lines};
-pub use crate::validation::ValidationConfig;
+pub use crate::domain::{
+    AgeBucket, AgeGroup, AgeGroupLabels, AgeGroupingMode, AgeOverRangePolicy, DuplicateEmailPolicy,
+    EmailError, EmailMaskPolicy, EmailProvider, EmptyLinePolicy, EnrichedUser, PipelineError,
+    RecordFilter, SortKey, User, UsernameScheme,
+};
+pub use crate::enrichers::{Enricher, EnrichmentData};
+pub use crate::input::{
+    process_reader, read_lines, InputLine, InputPart, InputSource, MemorySource,
+};
+pub use crate::logging::{init_logging, init_warning_counter, warnings_logged, LoggingMode};
+pub use crate::output::{LineEnding, MemorySink, OutputFormat, OutputOptions, OutputSink};
+pub use crate::parsing::ParseOptions;
+pub use crate::pipeline::{
+    dedup_key, dedup_key_with, enrich, parse_and_validate, process_line, process_lines,
+    process_lines_enriched, process_lines_observed, process_lines_to_writer,
+    process_lines_with_progress, sort_enriched, ProcessOutput, ProcessSummary, Progress,
+    RejectedLine,
+};
+pub use crate::rules::ValidationRule;
+pub use crate::run::{run_pipeline, RunOptions, RunReport};
+pub use crate::stats::{AgeGroupCounter, AgeHistogram, StageTimings};
+pub use crate::template::OutputTemplate;
+pub use crate::validation::{ValidationConfig, ValidationConfigBuilder, CONFIG_VERSION};
+
+use crate::parsing::{parse_age_lenient, split_fields, split_fixed_width, Column};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use tracing::{instrument, warn};
+
+/// Default upper bound for ages accepted by `validate_user`.
+///
+/// Override per run via `ValidationConfig::supported_age_ceiling`.
+pub const MAX_SUPPORTED_AGE: u8 = 120;
+
+/// Bucket labels produced by `AgeGroupingMode::Default` with the default (English)
+/// `AgeGroupLabels`; valid keys for label overrides.
+pub const DEFAULT_AGE_GROUP_LABELS: &[&str] = &["<teen", "teens", "20s", "30s", "40s", "50+"];
+
+/// Age group assigned, in every grouping mode, to users without an age (unless
+/// `AgeGroupLabels::unknown` is changed).
+pub const UNKNOWN_AGE_GROUP: &str = "unknown";
 
-use crate::validation::is_valid_email;
-use tracing::instrument;
+const MAX_INITIALS: usize = 3;
 
-const MAX_SUPPORTED_AGE: u8 = 120;
+/// Hex digits kept from the email digest for `UsernameScheme::Hash`.
+const HASH_USERNAME_LEN: usize = 8;
+/// Hex digits in `EnrichedUser::record_hash`.
+const RECORD_HASH_LEN: usize = 16;
+
+const GMAIL_DOMAINS: &[&str] = &["gmail.com", "googlemail.com"];
+const OUTLOOK_DOMAINS: &[&str] = &["outlook.com", "hotmail.com", "live.com", "msn.com"];
+const OTHER_CONSUMER_DOMAINS: &[&str] = &[
+    "yahoo.com",
+    "icloud.com",
+    "me.com",
+    "aol.com",
+    "proton.me",
+    "protonmail.com",
+    "gmx.com",
+    "gmx.de",
+    "mail.com",
+    "yandex.ru",
+];
 
 /// Parse a single CSV-like line into a `User` struct.
+///
+/// Fields may be double-quoted to embed commas; see [`parsing::split_fields`].
 #[instrument(level = "debug", skip(line), fields(line_len = line.len()))]
 pub fn parse_line(line: &str) -> Result<User, PipelineError> {
-    let mut parts = line.split(',').map(str::trim);
-    let name = parts
-        .next()
-        .filter(|s| !s.is_empty())
-        .ok_or_else(|| PipelineError::Parse {
-            reason: "missing name field".into(),
-        })?;
-    let age_str = parts.next().ok_or_else(|| PipelineError::Parse {
-        reason: "missing age field".into(),
-    })?;
-    let email = parts
-        .next()
-        .filter(|s| !s.is_empty())
-        .ok_or_else(|| PipelineError::Parse {
-            reason: "missing email field".into(),
+    parse_line_with(line, &ParseOptions::default())
+}
+
+/// Parse a single CSV-like line using explicit parse options.
+#[instrument(level = "debug", skip(line, opts), fields(line_len = line.len()))]
+pub fn parse_line_with(line: &str, opts: &ParseOptions) -> Result<User, PipelineError> {
+    let fields = match &opts.fixed_widths {
+        Some(widths) => split_fixed_width(line, widths)?,
+        None => split_fields(line, opts.delimiter)?,
+    };
+    let mut parts = fields.iter().map(|field| {
+        let value = if opts.trim_fields {
+            field.value.trim()
+        } else {
+            field.value.as_str()
+        };
+        (value, field.column)
+    });
+
+    let (mut name, mut age_field, mut email) = ("", ("", 0), "");
+    for column in opts.columns.columns() {
+        let part = parts.next().filter(|(s, _)| {
+            // An empty age is still present; it parses as "no age".
+            column == Column::Age || !s.trim().is_empty()
+        });
+        let part = part.ok_or_else(|| PipelineError::Parse {
+            reason: format!("missing {column} field"),
+            column: None,
         })?;
+        match column {
+            Column::Name => name = part.0,
+            Column::Age => age_field = part,
+            Column::Email => email = part.0,
+        }
+    }
+    let (age_str, age_column) = age_field;
 
-    if parts.next().is_some() {
+    if let Some((_, column)) = parts.next() {
         return Err(PipelineError::Parse {
             reason: "too many fields".into(),
+            column: Some(column),
         });
     }
 
-    let age: u8 = age_str.parse().map_err(|_| PipelineError::Parse {
-        reason: format!("invalid age `{age_str}`"),
-    })?;
+    let age = if age_str.trim().is_empty() {
+        None
+    } else {
+        let parsed_age = if opts.lenient_age {
+            parse_age_lenient(age_str)
+        } else {
+            age_str.trim().parse().ok()
+        };
+        let age: u8 = parsed_age.ok_or_else(|| PipelineError::Parse {
+            reason: format!("invalid age `{age_str}`"),
+            column: Some(age_column),
+        })?;
+        Some(age)
+    };
 
     Ok(User {
         name: name.to_owned(),
@@ -52,31 +179,27 @@ pub fn parse_line(line: &str) -> Result<User, PipelineError> {
     })
 }
 
-/// Apply validation rules to the parsed user.
+/// Apply validation rules (`ValidationConfig::rules`) to the parsed user, stopping at the first
+/// failure.
 #[instrument(level = "debug", skip(cfg))]
 pub fn validate_user(mut user: User, cfg: &ValidationConfig) -> Result<User, PipelineError> {
-    user.name = user.name.trim().to_owned();
-    if user.name.is_empty() {
-        return Err(PipelineError::EmptyName);
+    if cfg.parse.trim_fields {
+        user.name = user.name.trim().to_owned();
     }
-
-    if user.age < cfg.min_age {
-        return Err(PipelineError::InvalidAge {
-            age: user.age,
-            min_age: cfg.min_age,
-        });
+    if cfg.collapse_name_whitespace {
+        user.name = normalize_display_name(&user.name);
     }
-
-    if user.age > MAX_SUPPORTED_AGE {
-        return Err(PipelineError::AgeOutOfRange { age: user.age });
+    if cfg.age_over_range_policy == AgeOverRangePolicy::Clamp {
+        let ceiling = cfg.age_ceiling();
+        if let Some(age) = user.age.filter(|&age| age > ceiling) {
+            warn!(age, ceiling, "clamping age above the supported ceiling");
+            user.age = Some(ceiling);
+        }
     }
 
-    if !is_valid_email(&user.email, cfg.strict_email) {
-        return Err(PipelineError::InvalidEmail {
-            email: mask_email(&user.email),
-        });
+    for rule in cfg.rules() {
+        rule.check(&user)?;
     }
-
     Ok(user)
 }
 
@@ -86,46 +209,202 @@ pub fn enrich_user(user: User) -> EnrichedUser {
     enrich_user_with_mode(user, AgeGroupingMode::Default)
 }
 
-pub(crate) fn enrich_user_with_mode(user: User, mode: AgeGroupingMode) -> EnrichedUser {
-    let age_group = compute_age_group(user.age, mode);
-    let username = generate_username(&user);
+/// Annotate the user using an explicit age grouping strategy chosen at runtime.
+pub fn enrich_user_with_mode(user: User, mode: AgeGroupingMode) -> EnrichedUser {
+    let age_group = compute_age_group(
+        user.age,
+        mode,
+        validation::default_fine_grained_width(),
+        &[],
+        &AgeGroupLabels::default(),
+        &HashMap::new(),
+    );
+    build_enriched(user, age_group, UsernameScheme::Name)
+}
+
+fn build_enriched(user: User, age_group: AgeGroup, scheme: UsernameScheme) -> EnrichedUser {
+    let username = match scheme {
+        UsernameScheme::Name => generate_username(&user, false),
+        UsernameScheme::Hash => hash_username(&user.email),
+    };
+    let initials = compute_initials(&user.name);
+    let display_name = normalize_display_name(&user.name);
     EnrichedUser {
         user,
         age_group,
         username,
+        initials,
+        display_name,
+        provider: None,
+        record_hash: None,
+        extra: serde_json::Map::new(),
+    }
+}
+
+pub(crate) fn enrich_user_with_config(user: User, cfg: &ValidationConfig) -> EnrichedUser {
+    if cfg.skip_enrichment {
+        return EnrichedUser {
+            user,
+            age_group: AgeGroup::new(""),
+            username: String::new(),
+            initials: String::new(),
+            display_name: String::new(),
+            provider: None,
+            record_hash: None,
+            extra: serde_json::Map::new(),
+        };
+    }
+    let mut acc = EnrichmentData::default();
+    for enricher in cfg.enrichers() {
+        enricher.enrich(&user, &mut acc);
+    }
+    let mut enriched = EnrichedUser {
+        age_group: acc.age_group.unwrap_or_else(|| AgeGroup::new("")),
+        username: acc.username.unwrap_or_default(),
+        initials: compute_initials(&user.name),
+        display_name: normalize_display_name(&user.name),
+        provider: None,
+        record_hash: None,
+        extra: acc.extra,
+        user,
+    };
+    if cfg.classify_provider {
+        enriched.provider = Some(classify_provider(&enriched.user.email));
+    }
+    if cfg.record_hash {
+        enriched.record_hash = Some(record_hash(&enriched.user));
     }
+    enriched
 }
 
-fn compute_age_group(age: u8, mode: AgeGroupingMode) -> AgeGroup {
+/// First 64 bits, in hex, of the SHA-256 of the user's name (whitespace collapsed), age and
+/// email, with name and email trimmed and lowercased.
+///
+/// ```
+/// use monadic_pipeline::{parse_line, record_hash};
+///
+/// let a = record_hash(&parse_line("Alice  Smith,30,Alice@Example.com").unwrap());
+/// let b = record_hash(&parse_line(" alice smith ,30, alice@example.com").unwrap());
+/// assert_eq!(a, b);
+/// assert_eq!(a.len(), 16);
+/// ```
+pub fn record_hash(user: &User) -> String {
+    let age = user.age.map(|age| age.to_string()).unwrap_or_default();
+    let normalized = [
+        normalize_display_name(&user.name).to_lowercase(),
+        age,
+        user.email.trim().to_lowercase(),
+    ]
+    .join("\0");
+    let digest = Sha256::digest(normalized.as_bytes());
+    digest[..RECORD_HASH_LEN / 2]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Classify an email address by its domain.
+///
+/// Well-known consumer providers map to `Gmail`/`Outlook`, other consumer mailboxes and
+/// addresses without a domain map to `Other`, and everything else is assumed `Corporate`.
+///
+/// ```
+/// use monadic_pipeline::{classify_provider, EmailProvider};
+///
+/// assert_eq!(classify_provider("alice@gmail.com"), EmailProvider::Gmail);
+/// assert_eq!(classify_provider("bob@acme.io"), EmailProvider::Corporate);
+/// ```
+pub fn classify_provider(email: &str) -> EmailProvider {
+    let domain = match email.trim().rsplit_once('@') {
+        Some((_, domain)) if !domain.is_empty() => domain.to_ascii_lowercase(),
+        _ => return EmailProvider::Other,
+    };
+
+    if GMAIL_DOMAINS.contains(&domain.as_str()) {
+        EmailProvider::Gmail
+    } else if OUTLOOK_DOMAINS.contains(&domain.as_str()) {
+        EmailProvider::Outlook
+    } else if OTHER_CONSUMER_DOMAINS.contains(&domain.as_str()) {
+        EmailProvider::Other
+    } else {
+        EmailProvider::Corporate
+    }
+}
+
+/// Label for ages that no `AgeGroupingMode::Custom` bucket covers.
+pub const UNSPECIFIED_AGE_GROUP: &str = "unspecified";
+
+/// Bucket `age` under `mode`, taking labels from `labels`; `label_overrides` (keyed by the
+/// built-in English label) win over `labels` in `AgeGroupingMode::Default`,
+/// `AgeGroupingMode::FineGrained` buckets span `fine_grained_width` years, and
+/// `AgeGroupingMode::Custom` uses the first of `buckets` containing the age.
+pub(crate) fn compute_age_group(
+    age: Option<u8>,
+    mode: AgeGroupingMode,
+    fine_grained_width: u8,
+    buckets: &[AgeBucket],
+    labels: &AgeGroupLabels,
+    label_overrides: &HashMap<String, String>,
+) -> AgeGroup {
+    let Some(age) = age else {
+        return AgeGroup::new(labels.unknown.as_str());
+    };
     match mode {
         AgeGroupingMode::Default => {
-            let label = match age {
-                0..=12 => "<teen",
-                13..=19 => "teens",
-                20..=29 => "20s",
-                30..=39 => "30s",
-                40..=49 => "40s",
-                _ => "50+",
+            let (key, label) = match age {
+                0..=12 => ("<teen", &labels.under_teen),
+                13..=19 => ("teens", &labels.teens),
+                20..=29 => ("20s", &labels.twenties),
+                30..=39 => ("30s", &labels.thirties),
+                40..=49 => ("40s", &labels.forties),
+                _ => ("50+", &labels.fifty_plus),
             };
-            AgeGroup::new(label)
+            AgeGroup::new(label_overrides.get(key).unwrap_or(label).as_str())
         }
         AgeGroupingMode::FineGrained => {
-            let start = age / 5 * 5;
-            let end = (start + 4).min(MAX_SUPPORTED_AGE);
+            let width = fine_grained_width.max(1);
+            let start = age / width * width;
+            let cap = if age <= MAX_SUPPORTED_AGE {
+                MAX_SUPPORTED_AGE
+            } else {
+                u8::MAX
+            };
+            let end = start.saturating_add(width - 1).min(cap);
             AgeGroup::new(format!("{}-{}", start, end))
         }
         AgeGroupingMode::Wide => {
             let label = match age {
-                0..=17 => "young",
-                18..=45 => "adult",
-                _ => "senior",
+                0..=17 => &labels.young,
+                18..=45 => &labels.adult,
+                _ => &labels.senior,
             };
-            AgeGroup::new(label)
+            AgeGroup::new(label.as_str())
         }
+        AgeGroupingMode::Custom => buckets
+            .iter()
+            .find(|bucket| (bucket.min..=bucket.max).contains(&age))
+            .map_or_else(
+                || AgeGroup::new(UNSPECIFIED_AGE_GROUP),
+                |bucket| AgeGroup::new(bucket.label.as_str()),
+            ),
+    }
+}
+
+/// `email` without the `+tag` of its local part, e.g. `alice@example.com` for
+/// `alice+news@example.com`. Unchanged when nothing precedes the `+`, as in `+news@x.com`.
+pub(crate) fn strip_plus_tag(email: &str) -> Cow<'_, str> {
+    match email.split_once('@') {
+        Some((local, domain)) => match local.split_once('+') {
+            Some((base, _)) if !base.is_empty() => Cow::Owned(format!("{base}@{domain}")),
+            _ => Cow::Borrowed(email),
+        },
+        None => Cow::Borrowed(email),
     }
 }
 
-fn generate_username(user: &User) -> String {
+/// Username derived from the name, falling back to the email's local part (without a `+tag`
+/// when `strip_plus_tags` is set) for names without any ASCII alphanumerics.
+pub(crate) fn generate_username(user: &User, strip_plus_tags: bool) -> String {
     let mut raw = user
         .name
         .chars()
@@ -134,7 +413,12 @@ fn generate_username(user: &User) -> String {
         .to_ascii_lowercase();
     raw.retain(|c| c.is_ascii_alphanumeric());
     if raw.is_empty() {
-        user.email
+        let email = if strip_plus_tags {
+            strip_plus_tag(&user.email)
+        } else {
+            Cow::Borrowed(user.email.as_str())
+        };
+        email
             .split('@')
             .next()
             .map(|local| local.to_ascii_lowercase())
@@ -144,27 +428,92 @@ fn generate_username(user: &User) -> String {
     }
 }
 
+/// Short stable username for `UsernameScheme::Hash`; case and surrounding whitespace in the
+/// email do not change it.
+pub(crate) fn hash_username(email: &str) -> String {
+    let digest = Sha256::digest(email.trim().to_lowercase().as_bytes());
+    digest[..HASH_USERNAME_LEN / 2]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn compute_initials(name: &str) -> String {
+    name.split_whitespace()
+        .filter_map(|token| token.chars().find(|c| c.is_alphabetic()))
+        .take(MAX_INITIALS)
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+fn normalize_display_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 /// Format the enriched user for display or downstream consumption.
 #[instrument(level = "debug")]
 pub fn format_user(enriched: &EnrichedUser) -> String {
-    format!(
-        "{} ({}, {}) -> username={}",
-        enriched.user.name, enriched.user.age, enriched.age_group, enriched.username
-    )
+    output::render_user(enriched, false)
+}
+
+/// Format according to `cfg.output_format`, using the configured output template (falling
+/// back to `format_user`) for text output.
+pub(crate) fn format_with_config(enriched: &EnrichedUser, cfg: &ValidationConfig) -> String {
+    if cfg.skip_enrichment {
+        return output::user_row(&enriched.user, cfg.output_email_mask);
+    }
+    if let Some(meta) = &cfg.provenance {
+        return output::provenance_record(enriched, cfg.output_email_mask, meta);
+    }
+    match (cfg.output_format, &cfg.template) {
+        (OutputFormat::Csv, _) => output::csv_row(enriched, cfg.output_email_mask),
+        (OutputFormat::Json, _) => output::json_record(enriched, cfg.output_email_mask),
+        (OutputFormat::Text, Some(template)) => {
+            template.render_masked(enriched, cfg.output_email_mask)
+        }
+        (OutputFormat::Text, None) => output::render_user(enriched, cfg.color),
+    }
 }
 
 /// Mask the local part of an email address for logging.
 pub fn mask_email(email: &str) -> String {
+    mask_email_with(email, EmailMaskPolicy::Local)
+}
+
+/// Mask an email address according to `policy`.
+///
+/// ```
+/// use monadic_pipeline::{mask_email_with, EmailMaskPolicy};
+///
+/// assert_eq!(mask_email_with("user@example.com", EmailMaskPolicy::LocalAndDomain), "u***@e***.com");
+/// ```
+pub fn mask_email_with(email: &str, policy: EmailMaskPolicy) -> String {
     let trimmed = email.trim();
+    if policy == EmailMaskPolicy::None {
+        return trimmed.to_string();
+    }
     match trimmed.split_once('@') {
         Some((local, domain)) if !local.is_empty() && !domain.is_empty() => {
-            let visible = local.chars().next().unwrap_or('*');
-            format!("{}***@{}", visible, domain)
+            let local = mask_keep_first(local);
+            match policy {
+                EmailMaskPolicy::LocalAndDomain => match domain.rsplit_once('.') {
+                    Some((host, tld)) if !host.is_empty() => {
+                        format!("{local}@{}.{tld}", mask_keep_first(host))
+                    }
+                    _ => format!("{local}@***"),
+                },
+                _ => format!("{local}@{domain}"),
+            }
         }
         _ => "***".to_string(),
     }
 }
 
+fn mask_keep_first(part: &str) -> String {
+    let visible = part.chars().next().unwrap_or('*');
+    format!("{visible}***")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -174,7 +523,7 @@ mod tests {
     fn parse_line_success() {
         let user = parse_line("Alice,30,alice@example.com").expect("parse should succeed");
         assert_eq!(user.name, "Alice");
-        assert_eq!(user.age, 30);
+        assert_eq!(user.age, Some(30));
         assert_eq!(user.email, "alice@example.com");
     }
 
@@ -184,28 +533,972 @@ mod tests {
         assert!(matches!(err, PipelineError::Parse { .. }));
     }
 
+    #[test]
+    fn parse_line_accepts_quoted_fields() {
+        let user = parse_line(r#""Doe, Jane",30,"jane@example.com""#).expect("quoted fields parse");
+        assert_eq!(user.name, "Doe, Jane");
+        assert_eq!(user.email, "jane@example.com");
+    }
+
+    #[test]
+    fn parse_line_reports_unterminated_quote_column() {
+        let err = parse_line(r#"Alice,30,"alice@example.com"#).unwrap_err();
+        assert_eq!(
+            err,
+            PipelineError::Parse {
+                reason: "unterminated quote".into(),
+                column: Some(10),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "failed to parse line: unterminated quote at column 10"
+        );
+    }
+
+    #[test]
+    fn parse_line_reports_extra_field_column() {
+        let err = parse_line("Alice,30,alice@example.com,extra").unwrap_err();
+        assert!(matches!(
+            err,
+            PipelineError::Parse {
+                column: Some(28),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn lenient_age_accepts_tolerated_forms() {
+        for raw in [
+            "30", " 30 ", "+30", "30.0", "+30.00", "3_0", "+3_0.0", "0_3_0",
+        ] {
+            assert_eq!(parse_age_lenient(raw), Some(30), "input {raw:?}");
+        }
+    }
+
+    #[test]
+    fn lenient_age_rejects_invalid_forms() {
+        for raw in [
+            "", "+", "abc", "30.5", "30.", ".0", "-30", "++30", "300", "3 0", "_30", "30_", "3__0",
+            "3_0_", "30._0", "30.0_0", "3_00",
+        ] {
+            assert_eq!(parse_age_lenient(raw), None, "input {raw:?}");
+        }
+    }
+
+    #[test]
+    fn parse_line_with_lenient_age_is_opt_in() {
+        let line = "Alice,30.0,alice@example.com";
+        assert!(parse_line(line).is_err());
+
+        let opts = ParseOptions {
+            lenient_age: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            parse_line_with(line, &opts).expect("lenient parse").age,
+            Some(30)
+        );
+    }
+
+    #[test]
+    fn padded_name_is_trimmed_by_default() {
+        let cfg = ValidationConfig::default();
+        let out = process_line("  Alice  ,30,alice@example.com", &cfg).expect("valid line");
+        assert_eq!(out, "Alice (30, 30s) -> username=alice");
+    }
+
+    #[test]
+    fn padded_name_is_preserved_without_trimming() {
+        let mut cfg = ValidationConfig::default();
+        cfg.parse.trim_fields = false;
+        let user = parse_and_validate("  Alice  , 30 ,alice@example.com", &cfg).expect("valid");
+        assert_eq!(user.name, "  Alice  ");
+        assert_eq!(user.age, Some(30));
+
+        let err = parse_and_validate("   ,30,alice@example.com", &cfg).unwrap_err();
+        assert!(matches!(err, PipelineError::Parse { .. }));
+    }
+
+    #[test]
+    fn comment_detection_respects_configured_marker() {
+        let mut opts = ParseOptions::default();
+        assert!(!opts.is_comment("# note"));
+
+        opts.comment_char = Some('#');
+        assert!(opts.is_comment("# note"));
+        assert!(opts.is_comment("   #indented"));
+        assert!(!opts.is_comment("Alice,30,alice#1@example.com"));
+    }
+
     #[test]
     fn validate_user_rejects_underage() {
         let cfg = ValidationConfig {
             min_age: 21,
             strict_email: false,
             age_grouping: AgeGroupingMode::Default,
+            ..ValidationConfig::default()
         };
         let user = User {
             name: "Bob".into(),
-            age: 18,
+            age: Some(18),
             email: "bob@example.com".into(),
         };
         let err = validate_user(user, &cfg).unwrap_err();
         assert!(matches!(err, PipelineError::InvalidAge { .. }));
     }
 
+    #[test]
+    fn validate_user_honours_age_ceiling_override() {
+        let user = User {
+            name: "Methuselah".into(),
+            age: Some(130),
+            email: "m@example.com".into(),
+        };
+        let err = validate_user(user.clone(), &ValidationConfig::default()).unwrap_err();
+        assert_eq!(err, PipelineError::AgeOutOfRange { age: 130 });
+
+        let cfg = ValidationConfig {
+            supported_age_ceiling: Some(150),
+            ..ValidationConfig::default()
+        };
+        assert!(validate_user(user, &cfg).is_ok());
+    }
+
+    #[test]
+    fn config_rejects_ceiling_below_min_age() {
+        let cfg = ValidationConfig {
+            min_age: 30,
+            supported_age_ceiling: Some(20),
+            ..ValidationConfig::default()
+        };
+        assert!(matches!(
+            cfg.validate(),
+            Err(PipelineError::InvalidConfig { .. })
+        ));
+        assert!(ValidationConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn config_rejects_skip_enrichment_with_json_output() {
+        let cfg = ValidationConfig::from_json(
+            r#"{"config_version": 2, "skip_enrichment": true, "output_format": "json"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.validate().unwrap_err(),
+            PipelineError::InvalidConfig {
+                reason: "skip_enrichment writes CSV rows and cannot be combined with JSON output"
+                    .into()
+            }
+        );
+        let csv = ValidationConfig {
+            skip_enrichment: true,
+            output_format: OutputFormat::Csv,
+            ..ValidationConfig::default()
+        };
+        assert!(csv.validate().is_ok());
+    }
+
+    #[test]
+    fn builder_sets_fields_and_checks_invariants() {
+        let cfg = ValidationConfig::builder()
+            .min_age(21)
+            .max_age(65)
+            .strict_email(true)
+            .classify_provider(true)
+            .build()
+            .expect("valid builder config");
+        assert_eq!(cfg.min_age, 21);
+        assert_eq!(cfg.supported_age_ceiling, Some(65));
+        assert!(cfg.strict_email && cfg.classify_provider);
+
+        let err = ValidationConfig::builder()
+            .min_age(70)
+            .max_age(65)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, PipelineError::InvalidConfig { .. }));
+    }
+
+    #[test]
+    fn label_overrides_rename_default_buckets() {
+        let mut cfg = ValidationConfig::default();
+        cfg.label_overrides
+            .insert("50+".to_string(), "senior".to_string());
+        cfg.label_overrides
+            .insert("unknown-bucket".to_string(), "ignored".to_string());
+
+        let out = process_line("Carol,52,carol@example.com", &cfg).expect("valid");
+        assert_eq!(out, "Carol (52, senior) -> username=carol");
+        let out = process_line("Alice,30,alice@example.com", &cfg).expect("valid");
+        assert_eq!(out, "Alice (30, 30s) -> username=alice");
+        assert_eq!(cfg.unknown_label_overrides(), vec!["unknown-bucket"]);
+    }
+
+    #[test]
+    fn missing_age_is_rejected_unless_optional() {
+        let user = parse_line("Alice,,alice@example.com").expect("empty age parses");
+        assert_eq!(user.age, None);
+
+        let err = validate_user(user.clone(), &ValidationConfig::default()).unwrap_err();
+        assert_eq!(err, PipelineError::MissingAge);
+
+        let cfg = ValidationConfig {
+            min_age: 18,
+            require_age: false,
+            ..ValidationConfig::default()
+        };
+        let out = process_line("Alice, ,alice@example.com", &cfg).expect("age is optional");
+        assert_eq!(out, "Alice (?, unknown) -> username=alice");
+        let out = process_line("Bob,45,bob@example.com", &cfg).expect("present age");
+        assert_eq!(out, "Bob (45, 40s) -> username=bob");
+        assert!(matches!(
+            process_line("Tim,12,tim@example.com", &cfg),
+            Err(PipelineError::InvalidAge { age: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn strip_plus_tag_keeps_emails_without_a_base_local_part() {
+        assert_eq!(
+            strip_plus_tag("alice+news@example.com"),
+            "alice@example.com"
+        );
+        assert_eq!(strip_plus_tag("+a@x.com"), "+a@x.com");
+        assert_eq!(strip_plus_tag("+b@x.com"), "+b@x.com");
+        assert_eq!(strip_plus_tag("bob@example.com"), "bob@example.com");
+    }
+
+    #[test]
+    fn strip_plus_tags_applies_to_username_fallback() {
+        let user = User {
+            name: "李雷".into(),
+            age: Some(30),
+            email: "Ola+Work@example.com".into(),
+        };
+        let cfg = ValidationConfig::builder()
+            .strip_plus_tags(true)
+            .build()
+            .unwrap();
+        let enriched = enrich_user_with_config(user.clone(), &cfg);
+        assert_eq!(enriched.username, "ola");
+        assert_eq!(enriched.user.email, "Ola+Work@example.com");
+        let enriched = enrich_user_with_config(user, &ValidationConfig::default());
+        assert_eq!(enriched.username, "ola+work");
+    }
+
+    #[test]
+    fn record_hash_matches_for_identical_records_only() {
+        let cfg = ValidationConfig::builder()
+            .record_hash(true)
+            .build()
+            .unwrap();
+        let hash = |line: &str| {
+            let user = parse_and_validate(line, &cfg).expect("valid");
+            enrich_user_with_config(user, &cfg)
+                .record_hash
+                .expect("hash enabled")
+        };
+        let alice = hash("Alice,30,alice@example.com");
+        assert_eq!(alice, hash("Alice,30,alice@example.com"));
+        assert_eq!(alice, hash("ALICE , 30 ,Alice@Example.COM"));
+        assert_ne!(alice, hash("Alice,31,alice@example.com"));
+        assert_ne!(alice, hash("Alicia,30,alice@example.com"));
+        assert_ne!(alice, hash("Alice,30,alice@example.org"));
+        assert!(
+            enrich_user(parse_line("Alice,30,alice@example.com").unwrap())
+                .record_hash
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn hash_username_scheme_is_stable_and_per_email() {
+        let cfg = ValidationConfig {
+            username_scheme: UsernameScheme::Hash,
+            ..ValidationConfig::default()
+        };
+        let alice = |email: &str| {
+            let user = parse_and_validate(&format!("Alice,30,{email}"), &cfg).expect("valid");
+            enrich_user_with_config(user, &cfg).username
+        };
+
+        let first = alice("alice@example.com");
+        assert_eq!(first.len(), 8);
+        assert!(first.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_eq!(first, alice("alice@example.com"));
+        assert_eq!(first, alice("Alice@Example.com"));
+        assert_ne!(first, alice("alice2@example.com"));
+        // SHA-256("alice@example.com") starts with ff8d9819.
+        assert_eq!(first, "ff8d9819");
+    }
+
+    #[test]
+    fn custom_rules_run_after_builtin_rules() {
+        #[derive(Debug)]
+        struct RejectName(&'static str);
+
+        impl ValidationRule for RejectName {
+            fn check(&self, user: &User) -> Result<(), PipelineError> {
+                if user.name == self.0 {
+                    return Err(PipelineError::InvalidConfig {
+                        reason: format!("{} is blocked", self.0),
+                    });
+                }
+                Ok(())
+            }
+        }
+
+        let cfg = ValidationConfig::builder()
+            .min_age(18)
+            .rule(RejectName("Mallory"))
+            .build()
+            .expect("valid config");
+        assert_eq!(cfg.rules().len(), 4);
+
+        let err = process_line("Mallory,30,mallory@example.com", &cfg).unwrap_err();
+        assert_eq!(
+            err,
+            PipelineError::InvalidConfig {
+                reason: "Mallory is blocked".into()
+            }
+        );
+        // Built-in checks still run first.
+        assert!(matches!(
+            process_line("Mallory,12,mallory@example.com", &cfg),
+            Err(PipelineError::InvalidAge { .. })
+        ));
+        assert!(process_line("Alice,30,alice@example.com", &cfg).is_ok());
+    }
+
+    #[test]
+    fn custom_enrichers_add_fields_after_builtin_ones() {
+        #[derive(Debug)]
+        struct EmailDomain;
+
+        impl Enricher for EmailDomain {
+            fn enrich(&self, user: &User, acc: &mut EnrichmentData) {
+                if let Some((_, domain)) = user.email.split_once('@') {
+                    acc.extra.insert("domain".into(), domain.into());
+                }
+                // Sees the username derived by the built-in enricher.
+                acc.username = acc.username.take().map(|name| format!("{name}-ext"));
+            }
+        }
+
+        let cfg = ValidationConfig::builder()
+            .enricher(EmailDomain)
+            .build()
+            .expect("valid config");
+        assert_eq!(cfg.enrichers().len(), 3);
+
+        let records =
+            process_lines_enriched(vec!["Alice,30,alice@example.com".to_string()], &cfg).unwrap();
+        assert_eq!(records[0].username, "alice-ext");
+        assert_eq!(records[0].age_group.label(), "30s");
+        assert_eq!(records[0].extra["domain"], "example.com");
+
+        let json = serde_json::to_value(&records[0]).unwrap();
+        assert_eq!(json["extra"]["domain"], "example.com");
+        let plain = enrich_user(parse_line("Bob,45,bob@example.com").unwrap());
+        assert!(serde_json::to_value(&plain).unwrap().get("extra").is_none());
+    }
+
+    #[test]
+    fn collapse_name_whitespace_normalises_internal_runs() {
+        let line = "Alice \t  Wonder   Land,30,alice@example.com";
+        let preserved = process_line(line, &ValidationConfig::default()).unwrap();
+        assert_eq!(
+            preserved,
+            "Alice \t  Wonder   Land (30, 30s) -> username=alicewonderland"
+        );
+
+        let cfg = ValidationConfig::builder()
+            .collapse_name_whitespace(true)
+            .build()
+            .unwrap();
+        let user = parse_and_validate(line, &cfg).unwrap();
+        assert_eq!(user.name, "Alice Wonder Land");
+        let enriched = enrich_user_with_config(user, &cfg);
+        assert_eq!(enriched.initials, "AWL");
+        assert_eq!(
+            format_user(&enriched),
+            "Alice Wonder Land (30, 30s) -> username=alicewonderland"
+        );
+    }
+
+    #[test]
+    fn color_only_adds_escape_codes() {
+        let line = "Alice,30,alice@example.com";
+        let plain = process_line(line, &ValidationConfig::default()).unwrap();
+        let cfg = ValidationConfig::builder().color(true).build().unwrap();
+        let colored = process_line(line, &cfg).unwrap();
+        assert_ne!(colored, plain);
+        let stripped = regex::Regex::new("\x1b\\[[0-9;]*m")
+            .unwrap()
+            .replace_all(&colored, "");
+        assert_eq!(stripped, plain);
+    }
+
+    #[test]
+    fn parse_line_honours_column_order() {
+        let opts = ParseOptions {
+            columns: "email,name,age".parse().expect("valid column order"),
+            ..ParseOptions::default()
+        };
+        let user = parse_line_with("alice@example.com,Alice,30", &opts).expect("reordered line");
+        assert_eq!(user.name, "Alice");
+        assert_eq!(user.age, Some(30));
+        assert_eq!(user.email, "alice@example.com");
+
+        let err = parse_line_with(",Alice,30", &opts).unwrap_err();
+        assert_eq!(err.to_string(), "failed to parse line: missing email field");
+        let err = parse_line_with("alice@example.com,Alice,3x", &opts).unwrap_err();
+        assert_eq!(
+            err,
+            PipelineError::Parse {
+                reason: "invalid age `3x`".into(),
+                column: Some(25)
+            }
+        );
+    }
+
+    #[test]
+    fn age_group_labels_can_be_translated() {
+        let german = AgeGroupLabels {
+            teens: "Jugendliche".into(),
+            thirties: "30er".into(),
+            adult: "Erwachsene".into(),
+            unknown: "unbekannt".into(),
+            ..AgeGroupLabels::default()
+        };
+        let mut cfg = ValidationConfig::builder()
+            .labels(german)
+            .label_override("30s", "Dreißiger")
+            .require_age(false)
+            .build()
+            .expect("valid config");
+        let group = |line: &str, cfg: &ValidationConfig| {
+            let user = parse_and_validate(line, cfg).expect("valid user");
+            enrich_user_with_config(user, cfg).age_group.to_string()
+        };
+
+        assert_eq!(group("Anna,16,anna@example.de", &cfg), "Jugendliche");
+        assert_eq!(group("Ben,,ben@example.de", &cfg), "unbekannt");
+        assert_eq!(group("Carl,55,carl@example.de", &cfg), "50+");
+        // Overrides keyed by the English label still take precedence.
+        assert_eq!(group("Dora,35,dora@example.de", &cfg), "Dreißiger");
+
+        cfg.age_grouping = AgeGroupingMode::Wide;
+        assert_eq!(group("Emil,30,emil@example.de", &cfg), "Erwachsene");
+    }
+
+    #[test]
+    fn age_over_range_policy_clamps_to_ceiling() {
+        let strict = ValidationConfig::default();
+        assert_eq!(
+            process_line("Old,200,old@example.com", &strict).unwrap_err(),
+            PipelineError::AgeOutOfRange { age: 200 }
+        );
+
+        let cfg = ValidationConfig {
+            age_over_range_policy: AgeOverRangePolicy::Clamp,
+            ..ValidationConfig::default()
+        };
+        let user = parse_and_validate("Old,200,old@example.com", &cfg).expect("age is clamped");
+        assert_eq!(user.age, Some(MAX_SUPPORTED_AGE));
+        let capped = ValidationConfig {
+            supported_age_ceiling: Some(99),
+            ..cfg
+        };
+        let user = parse_and_validate("Old,120,old@example.com", &capped).expect("age is clamped");
+        assert_eq!(user.age, Some(99));
+    }
+
+    #[test]
+    fn csv_output_round_trips_through_the_parser() {
+        for name in ["Alice", "Doe, Jane", r#"Jane "JJ" Doe"#, "a,\"b\",c"] {
+            let line = format!("{},30,jane@example.com", output::csv_field(name));
+            let user = parse_line(&line).expect("encoded line should parse");
+            assert_eq!(user.name, name);
+        }
+
+        let mut enriched = enrich_user(parse_line(r#""Doe, Jane",30,jane@example.com"#).unwrap());
+        enriched.username = "doe,\"jane\"".into();
+        let row = output::csv_row(&enriched, EmailMaskPolicy::None);
+        assert_eq!(row, r#""Doe, Jane",30,jane@example.com,"doe,""jane""",30s"#);
+        let values: Vec<String> = split_fields(&row, ',')
+            .expect("row should split")
+            .into_iter()
+            .map(|field| field.value)
+            .collect();
+        assert_eq!(
+            values,
+            ["Doe, Jane", "30", "jane@example.com", "doe,\"jane\"", "30s"]
+        );
+    }
+
+    #[test]
+    fn render_formats_a_single_record() {
+        let enriched = enrich_user(parse_line(r#""Doe, Jane",30,jane@example.com"#).unwrap());
+        assert_eq!(
+            output::render(&enriched, OutputFormat::Text),
+            "Doe, Jane (30, 30s) -> username=doejane"
+        );
+        assert_eq!(
+            output::render(&enriched, OutputFormat::Csv),
+            r#""Doe, Jane",30,jane@example.com,doejane,30s"#
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&output::render(&enriched, OutputFormat::Json)).unwrap();
+        assert_eq!(json["user"]["name"], "Doe, Jane");
+        assert_eq!(json["username"], "doejane");
+        assert_eq!(json["age_group"]["label"], "30s");
+
+        assert_eq!(output::render_header(OutputFormat::Text, false), None);
+        assert_eq!(output::render_header(OutputFormat::Json, false), None);
+    }
+
+    #[test]
+    fn render_header_matches_csv_row_columns() {
+        let field_count = |line: &str| split_fields(line, ',').unwrap().len();
+        let mut enriched = enrich_user(parse_line(r#""Doe, Jane",30,jane@example.com"#).unwrap());
+        for with_hash in [false, true] {
+            enriched.record_hash = with_hash.then(|| record_hash(&enriched.user));
+            let header = output::render_header(OutputFormat::Csv, with_hash).unwrap();
+            let row = output::render(&enriched, OutputFormat::Csv);
+            assert_eq!(
+                field_count(header),
+                field_count(&row),
+                "with_hash: {with_hash}"
+            );
+        }
+    }
+
     #[test]
     fn mask_email_obscures_local_part() {
         assert_eq!(mask_email("user@example.com"), "u***@example.com");
         assert_eq!(mask_email("invalid"), "***");
     }
 
+    #[test]
+    fn mask_email_with_applies_each_policy() {
+        let email = " user@mail.example.com ";
+        assert_eq!(
+            mask_email_with(email, EmailMaskPolicy::None),
+            "user@mail.example.com"
+        );
+        assert_eq!(
+            mask_email_with(email, EmailMaskPolicy::Local),
+            "u***@mail.example.com"
+        );
+        assert_eq!(
+            mask_email_with(email, EmailMaskPolicy::LocalAndDomain),
+            "u***@m***.com"
+        );
+        assert_eq!(
+            mask_email_with("user@localhost", EmailMaskPolicy::LocalAndDomain),
+            "u***@***"
+        );
+        assert_eq!(
+            mask_email_with("invalid", EmailMaskPolicy::LocalAndDomain),
+            "***"
+        );
+    }
+
+    #[test]
+    fn email_mask_policies_apply_to_output_and_errors_separately() {
+        let template: OutputTemplate = "{username} <{email}>".parse().unwrap();
+        let cfg = ValidationConfig {
+            template: Some(template),
+            output_email_mask: EmailMaskPolicy::Local,
+            log_email_mask: EmailMaskPolicy::LocalAndDomain,
+            ..ValidationConfig::default()
+        };
+        let out = process_line("Alice,30,alice@example.com", &cfg).expect("valid");
+        assert_eq!(out, "alice <a***@example.com>");
+
+        let err = process_line("Alice,30,alice@localhost", &cfg).unwrap_err();
+        assert!(matches!(
+            err,
+            PipelineError::InvalidEmail { ref email, .. } if email == "a***@***"
+        ));
+    }
+
+    #[test]
+    fn classify_provider_recognises_known_domains() {
+        assert_eq!(classify_provider("a@gmail.com"), EmailProvider::Gmail);
+        assert_eq!(classify_provider("a@GoogleMail.com"), EmailProvider::Gmail);
+        assert_eq!(classify_provider("a@hotmail.com"), EmailProvider::Outlook);
+        assert_eq!(classify_provider("a@yahoo.com"), EmailProvider::Other);
+        assert_eq!(classify_provider("a@example.com"), EmailProvider::Corporate);
+        assert_eq!(classify_provider("not-an-email"), EmailProvider::Other);
+    }
+
+    #[test]
+    fn provider_is_opt_in() {
+        let user = User {
+            name: "Alice".into(),
+            age: Some(30),
+            email: "alice@gmail.com".into(),
+        };
+        let mut cfg = ValidationConfig::default();
+        assert_eq!(enrich_user_with_config(user.clone(), &cfg).provider, None);
+
+        cfg.classify_provider = true;
+        let enriched = enrich_user_with_config(user, &cfg);
+        assert_eq!(enriched.provider, Some(EmailProvider::Gmail));
+        assert!(format_user(&enriched).ends_with("provider=gmail"));
+    }
+
+    #[test]
+    fn initials_use_first_letter_of_each_token() {
+        assert_eq!(compute_initials("Alice Wonderland"), "AW");
+        assert_eq!(compute_initials("alice"), "A");
+        assert_eq!(compute_initials("Mary-Jane Watson"), "MW");
+        assert_eq!(compute_initials("'Bob' O'Neil"), "BO");
+        assert_eq!(compute_initials("Jean Luc Picard Jr"), "JLP");
+    }
+
+    #[test]
+    fn display_name_collapses_whitespace() {
+        assert_eq!(
+            normalize_display_name("  Alice   Wonderland "),
+            "Alice Wonderland"
+        );
+    }
+
+    #[test]
+    fn template_renders_all_placeholders() {
+        let template = OutputTemplate::parse("{name}|{age}|{age_group}|{username}|{email} {{x}}")
+            .expect("valid template");
+        let enriched = enrich_user(parse_line("Alice,30,alice@example.com").unwrap());
+        assert_eq!(
+            template.render(&enriched),
+            "Alice|30|30s|alice|alice@example.com {x}"
+        );
+    }
+
+    #[test]
+    fn template_rejects_unknown_or_unclosed_placeholders() {
+        for source in ["{nickname}", "{name", "name}"] {
+            let err = OutputTemplate::parse(source).unwrap_err();
+            assert!(
+                matches!(err, PipelineError::InvalidConfig { .. }),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn strict_email_accepts_idn_only_when_allowed() {
+        let mut cfg = ValidationConfig {
+            strict_email: true,
+            ..ValidationConfig::default()
+        };
+        assert!(!validation::is_valid_email_with("user@münchen.de", &cfg));
+
+        cfg.allow_idn = true;
+        assert!(validation::is_valid_email_with("user@münchen.de", &cfg));
+        assert!(validation::is_valid_email_with("user@例え.テスト", &cfg));
+        assert!(!validation::is_valid_email_with("user@-bad.de", &cfg));
+    }
+
+    #[test]
+    fn invalid_email_reports_reason() {
+        let cfg = ValidationConfig {
+            strict_email: true,
+            ..ValidationConfig::default()
+        };
+        let cases = [
+            ("", EmailError::Empty),
+            ("alice.example.com", EmailError::MissingAt),
+            ("a@b@example.com", EmailError::MultipleAt),
+            ("@example.com", EmailError::EmptyLocal),
+            ("alice@", EmailError::EmptyDomain),
+            ("alice@localhost", EmailError::MissingDomainDot),
+            ("alice!@example.com", EmailError::RegexMismatch),
+        ];
+        for (email, expected) in cases {
+            assert_eq!(
+                validation::validate_email_detailed(email, &cfg),
+                Err(expected)
+            );
+        }
+        let long_local = format!("{}@example.com", "a".repeat(65));
+        assert_eq!(
+            validation::validate_email_detailed(&long_local, &cfg),
+            Err(EmailError::LocalTooLong)
+        );
+
+        let err = process_line("Alice,30,alice@localhost", &cfg).unwrap_err();
+        assert_eq!(
+            err,
+            PipelineError::InvalidEmail {
+                email: mask_email("alice@localhost"),
+                reason: EmailError::MissingDomainDot,
+            }
+        );
+        assert!(err.to_string().ends_with("(domain has no `.`)"));
+    }
+
+    #[test]
+    fn parse_line_with_tab_delimiter_keeps_quoted_tabs() {
+        let opts = ParseOptions {
+            delimiter: '\t',
+            ..ParseOptions::default()
+        };
+        let user = parse_line_with("\"Doe\tJane\"\t30\tjane@example.com", &opts).unwrap();
+        assert_eq!(user.name, "Doe\tJane");
+        assert_eq!(user.age, Some(30));
+
+        let user = parse_line_with("Doe, Jane\t30\tjane@example.com", &opts).unwrap();
+        assert_eq!(user.name, "Doe, Jane");
+        assert!(parse_line_with("Jane,30,jane@example.com", &opts).is_err());
+        assert!(parse_line_with("\"Doe\tJane\t30\tjane@example.com", &opts).is_err());
+    }
+
+    #[test]
+    fn parse_line_with_fixed_widths_follows_column_order() {
+        let opts = ParseOptions {
+            fixed_widths: Some(vec![12, 6, 3]),
+            columns: "email,name,age".parse().unwrap(),
+            ..ParseOptions::default()
+        };
+        let user = parse_line_with("a@b.co      Ann   41 ", &opts).unwrap();
+        assert_eq!((user.name.as_str(), user.age), ("Ann", Some(41)));
+        assert_eq!(user.email, "a@b.co");
+
+        let err = parse_line_with("a@b.co      Ann   41 extra", &opts).unwrap_err();
+        assert_eq!(
+            err,
+            PipelineError::Parse {
+                reason: "too many fields".into(),
+                column: Some(22),
+            }
+        );
+    }
+
+    #[test]
+    fn reject_zero_age_flags_zero_without_min_age() {
+        let line = "Baby,0,baby@example.com";
+        assert!(process_line(line, &ValidationConfig::default()).is_ok());
+
+        let cfg = ValidationConfig::builder()
+            .reject_zero_age(true)
+            .build()
+            .unwrap();
+        let err = process_line(line, &cfg).unwrap_err();
+        assert_eq!(err, PipelineError::InvalidAge { age: 0, min_age: 0 });
+        assert_eq!(
+            err.to_string(),
+            "age 0 is rejected as a likely placeholder (reject_zero_age)"
+        );
+        assert!(process_line("Kid,1,kid@example.com", &cfg).is_ok());
+
+        let cfg = ValidationConfig::builder()
+            .min_age(18)
+            .reject_zero_age(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            process_line(line, &cfg).unwrap_err().to_string(),
+            "age 0 is below configured minimum 18"
+        );
+    }
+
+    #[test]
+    fn fine_grained_width_sets_bucket_size() {
+        let cfg = ValidationConfig::builder()
+            .age_grouping(AgeGroupingMode::FineGrained)
+            .fine_grained_width(10)
+            .build()
+            .unwrap();
+        let group = |age| {
+            let user = User {
+                name: "Alice".into(),
+                age: Some(age),
+                email: "alice@example.com".into(),
+            };
+            enrich_user_with_config(user, &cfg).age_group
+        };
+        assert_eq!(group(34).label(), "30-39");
+        assert_eq!(group(120).label(), "120-120");
+
+        assert!(ValidationConfig::builder()
+            .fine_grained_width(0)
+            .build()
+            .is_err());
+        assert!(ValidationConfig::builder()
+            .fine_grained_width(121)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn custom_age_buckets_leave_gaps_unspecified() {
+        let bucket = |min, max, label: &str| AgeBucket {
+            min,
+            max,
+            label: label.into(),
+        };
+        let cfg = ValidationConfig::builder()
+            .age_buckets(vec![bucket(50, 120, "senior"), bucket(0, 17, "minor")])
+            .build()
+            .unwrap();
+        let group = |age| {
+            let user = User {
+                name: "Alice".into(),
+                age: Some(age),
+                email: "alice@example.com".into(),
+            };
+            enrich_user_with_config(user, &cfg).age_group
+        };
+        assert_eq!(group(17).label(), "minor");
+        assert_eq!(group(50).label(), "senior");
+        assert_eq!(group(30).label(), UNSPECIFIED_AGE_GROUP);
+
+        let err = ValidationConfig::builder()
+            .age_buckets(vec![bucket(0, 17, "minor"), bucket(17, 30, "youth")])
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            PipelineError::InvalidConfig {
+                reason: "age buckets `minor` (0-17) and `youth` (17-30) overlap".into()
+            }
+        );
+        assert!(ValidationConfig::builder()
+            .age_buckets(vec![bucket(30, 20, "backwards")])
+            .build()
+            .is_err());
+        assert!(ValidationConfig::builder()
+            .age_grouping(AgeGroupingMode::Custom)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn lax_email_requires_min_domain_labels() {
+        let mut cfg = ValidationConfig::default();
+        assert_eq!(
+            validation::validate_email_detailed("a@b", &cfg),
+            Err(EmailError::MissingDomainDot)
+        );
+        assert_eq!(
+            validation::validate_email_detailed("a@.c", &cfg),
+            Err(EmailError::TooFewDomainLabels { min: 2 })
+        );
+        assert_eq!(
+            validation::validate_email_detailed("a@b.c", &cfg),
+            Err(EmailError::TldTooShort { min: 2 })
+        );
+        assert!(validation::is_valid_email_with("a@b.co", &cfg));
+
+        cfg.min_domain_labels = 3;
+        assert_eq!(
+            validation::validate_email_detailed("a@b.co", &cfg),
+            Err(EmailError::TooFewDomainLabels { min: 3 })
+        );
+        assert!(validation::is_valid_email_with("a@mail.b.co", &cfg));
+
+        cfg.min_domain_labels = 2;
+        cfg.min_tld_len = 1;
+        assert!(validation::is_valid_email_with("a@b.c", &cfg));
+    }
+
+    #[test]
+    fn every_error_variant_has_a_hint() {
+        let cases = [
+            (
+                PipelineError::Parse {
+                    reason: "x".into(),
+                    column: None,
+                },
+                "check that the line has `name,age,email` fields and balanced double quotes",
+            ),
+            (
+                PipelineError::EmptyName,
+                "fill in the name field or remove the record",
+            ),
+            (
+                PipelineError::MissingAge,
+                "fill in the age or pass --optional-age",
+            ),
+            (
+                PipelineError::InvalidAge {
+                    age: 10,
+                    min_age: 18,
+                },
+                "lower --min-age or fix the record",
+            ),
+            (
+                PipelineError::InvalidAge { age: 0, min_age: 0 },
+                "fix the record or disable reject_zero_age",
+            ),
+            (
+                PipelineError::AgeOutOfRange { age: 200 },
+                "fix the record or pass --age-over-range clamp",
+            ),
+            (
+                PipelineError::InvalidEmail {
+                    email: "a***@x".into(),
+                    reason: EmailError::MissingDomainDot,
+                },
+                "fix the address or remove the record",
+            ),
+            (
+                PipelineError::ErrorRateExceeded {
+                    failed: 3,
+                    total: 4,
+                },
+                "check that the input has the expected format, or raise --max-error-rate",
+            ),
+            (
+                PipelineError::DuplicateEmail {
+                    email: "a***@x.com".into(),
+                },
+                "remove the repeated record or pass --duplicate-emails warn",
+            ),
+            (
+                PipelineError::Io { reason: "x".into() },
+                "check that the input exists and is readable",
+            ),
+            (
+                PipelineError::Output { reason: "x".into() },
+                "check that the output destination is writable and has space",
+            ),
+            (
+                PipelineError::InvalidConfig { reason: "x".into() },
+                "check the command-line flags and configuration values",
+            ),
+        ];
+        for (err, expected) in cases {
+            assert_eq!(err.hint(), Some(expected), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn known_tlds_restrict_email_domains() {
+        let cfg = ValidationConfig::builder()
+            .min_age(0)
+            .valid_tlds(["com", ".JP"])
+            .build()
+            .expect("valid config");
+        assert!(validation::is_valid_email_with("user@example.com", &cfg));
+        assert!(validation::is_valid_email_with("user@example.co.jp", &cfg));
+        assert_eq!(
+            validation::validate_email_detailed("user@foo.zzz", &cfg),
+            Err(EmailError::UnknownTld)
+        );
+        assert!(validation::is_valid_email_with(
+            "user@foo.zzz",
+            &ValidationConfig::default()
+        ));
+    }
+
     #[test]
     fn strict_email_accepts_valid() {
         assert!(validation::is_valid_email("alice@example.com", true));
@@ -218,7 +1511,7 @@ mod tests {
             let line = format!("{name},{age},{email}");
             let user = parse_line(&line).expect("valid synthetic input");
             prop_assert_eq!(user.name, name);
-            prop_assert_eq!(user.age, age);
+            prop_assert_eq!(user.age, Some(age));
             prop_assert_eq!(user.email, email);
         }
 
@@ -230,10 +1523,11 @@ mod tests {
                 min_age: 0,
                 strict_email: true,
                 age_grouping: AgeGroupingMode::Default,
+                ..ValidationConfig::default()
             };
             let user = User {
                 name: "Tester".into(),
-                age: 30,
+                age: Some(30),
                 email,
             };
             let result = validate_user(user, &cfg);
@@ -1,6 +1,14 @@
 use anyhow::{anyhow, Result};
 use clap::ValueEnum;
-use tracing_subscriber::{fmt, EnvFilter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// `WARN` events logged by this crate since a subscriber was installed.
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
 
 /// Log formatting modes supported by the binary.
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -10,6 +18,9 @@ pub enum LoggingMode {
 }
 
 /// Initialise tracing/logging based on the requested mode and feature flags.
+///
+/// Logs are written to stderr so stdout carries only pipeline output. Warnings are counted for
+/// [`warnings_logged`] whatever the log filter.
 pub fn init_logging(mode: LoggingMode) -> Result<()> {
     match mode {
         LoggingMode::Human => init_human(),
@@ -19,9 +30,13 @@ pub fn init_logging(mode: LoggingMode) -> Result<()> {
 
 #[cfg(feature = "human-logs")]
 fn init_human() -> Result<()> {
-    fmt()
-        .with_env_filter(env_filter())
+    let logs = tracing_subscriber::fmt::layer()
         .with_target(false)
+        .with_writer(std::io::stderr)
+        .with_filter(env_filter());
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(warning_counter())
         .try_init()
         .map_err(|err| anyhow!("failed to install human log subscriber: {err}"))
 }
@@ -33,10 +48,14 @@ fn init_human() -> Result<()> {
 
 #[cfg(feature = "json-logs")]
 fn init_json() -> Result<()> {
-    fmt()
+    let logs = tracing_subscriber::fmt::layer()
         .json()
-        .with_env_filter(env_filter())
         .with_target(false)
+        .with_writer(std::io::stderr)
+        .with_filter(env_filter());
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(warning_counter())
         .try_init()
         .map_err(|err| anyhow!("failed to install json log subscriber: {err}"))
 }
@@ -46,6 +65,40 @@ fn init_json() -> Result<()> {
     Err(anyhow!("json logging support is disabled at compile time"))
 }
 
-fn env_filter() -> EnvFilter {
-    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("monadic_pipeline=info"))
+#[cfg(any(feature = "human-logs", feature = "json-logs"))]
+fn env_filter() -> tracing_subscriber::EnvFilter {
+    tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("monadic_pipeline=info"))
+}
+
+/// Count warnings for [`warnings_logged`] without writing any logs, e.g. for a quiet run that
+/// still has to fail on warnings.
+pub fn init_warning_counter() -> Result<()> {
+    tracing_subscriber::registry()
+        .with(warning_counter())
+        .try_init()
+        .map_err(|err| anyhow!("failed to install warning counter: {err}"))
+}
+
+/// How many `WARN` events this crate has logged since [`init_logging`] or
+/// [`init_warning_counter`]; always 0 without either.
+pub fn warnings_logged() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+struct WarningCounter;
+
+impl<S: Subscriber> Layer<S> for WarningCounter {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() == Level::WARN {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn warning_counter<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    WarningCounter.with_filter(Targets::new().with_target("monadic_pipeline", Level::WARN))
 }
@@ -1,26 +1,97 @@
 #![deny(unsafe_code)]
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use monadic_pipeline::input::{read_numbered_lines, stream_numbered_lines};
+use monadic_pipeline::parsing::{split_fields, ColumnOrder};
+use monadic_pipeline::warnings_logged;
 use monadic_pipeline::{
-    init_logging, process_lines, AgeGroupingMode, LoggingMode, ValidationConfig,
+    generate, init_logging, init_warning_counter, output, process_lines_to_writer, run_pipeline,
+    AgeBucket, AgeGroupLabels, AgeGroupingMode, AgeOverRangePolicy, DuplicateEmailPolicy,
+    EmailMaskPolicy, EmptyLinePolicy, InputLine, InputPart, InputSource, LineEnding, LoggingMode,
+    OutputFormat, OutputOptions, OutputSink, OutputTemplate, ParseOptions, PipelineError,
+    ProcessSummary, RunOptions, RunReport, SortKey, UsernameScheme, ValidationConfig,
 };
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufReader, BufWriter, LineWriter, Write};
 use std::path::{Path, PathBuf};
-use tracing::{info, warn};
+use std::process::ExitCode;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tracing::warn;
 
 #[derive(Debug, Parser)]
-#[command(name = "monadic-pipeline", version, about = "Monadic pipeline demo for CSV-like data", long_about = None)]
+#[command(
+    name = "monadic-pipeline",
+    version,
+    about = "Monadic pipeline demo for CSV-like data",
+    long_about = None,
+    after_help = EXIT_CODES_HELP,
+    args_conflicts_with_subcommands = true
+)]
 struct Cli {
-    /// Input source: file path, directory, or '-' for stdin.
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Flags for the default `run` subcommand, so `monadic-pipeline --in users.csv` keeps
+    /// working without naming it.
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// Flags of the `run` subcommand: input, configuration and output of a pipeline run.
+#[derive(Debug, Clone, Args)]
+struct RunArgs {
+    /// Input source: file path, directory, http(s) URL (requires the `http` feature), or '-' for
+    /// stdin. Repeat to concatenate inputs in order.
     #[arg(long = "in", value_name = "PATH", default_value = "-")]
-    input: String,
+    inputs: Vec<String>,
+
+    /// JSON config file (see `ValidationConfig::from_json`); command-line flags override it.
+    #[arg(long = "config", value_name = "FILE")]
+    config: Option<PathBuf>,
 
     /// Optional output file (defaults to stdout).
     #[arg(long = "out", value_name = "FILE")]
     output: Option<PathBuf>,
 
+    /// Skip lines that fail to parse or validate, and input files that cannot be read, instead
+    /// of aborting, then print a `processed X, failed Y` summary (with a per-file breakdown for
+    /// several files) to stderr. Exits with 5 if anything was skipped.
+    #[arg(long = "continue-on-error")]
+    continue_on_error: bool,
+
+    /// Write one JSON object per rejected line (`{"line":N,"error":"...","kind":"..."}`) to
+    /// this file.
+    #[arg(
+        long = "errors-out",
+        value_name = "FILE",
+        requires = "continue_on_error"
+    )]
+    errors_out: Option<PathBuf>,
+
+    /// With `--continue-on-error`, stop with exit code 6 once more than this fraction (0.0 to
+    /// 1.0) of the lines read were rejected (checked after the first 20 lines).
+    #[arg(long = "max-error-rate", value_name = "RATE")]
+    max_error_rate: Option<f64>,
+
+    /// Include the text of each rejected line, with emails masked per `--log-email-mask`, in
+    /// the rejection log and `--errors-out` records (as `line_text`).
+    #[arg(long = "with-line-text", requires = "continue_on_error")]
+    with_line_text: bool,
+
+    /// Line terminator for output records.
+    #[arg(long = "line-ending", value_enum, default_value_t = LineEnding::Lf)]
+    line_ending: LineEnding,
+
+    /// Do not terminate the last output record.
+    #[arg(long = "no-trailing-newline")]
+    no_trailing_newline: bool,
+
     /// Minimum allowed age.
     #[arg(long = "min-age", value_name = "AGE")]
     min_age: Option<u8>,
@@ -29,57 +100,906 @@ struct Cli {
     #[arg(long = "strict-email")]
     strict_email: bool,
 
+    /// Be strict about everything: implies `--strict-email`, `--strict-blank-lines` and
+    /// `--duplicate-emails error`. Extra fields and ages above the maximum are always rejected.
+    /// An explicit `--duplicate-emails` still takes precedence.
+    #[arg(long = "strict")]
+    strict: bool,
+
+    /// Accept internationalized (Unicode) domains in strict email mode.
+    #[arg(long = "allow-idn")]
+    allow_idn: bool,
+
+    /// Accept ages like `+30`, `3_0` or `30.0`.
+    #[arg(long = "lenient-age")]
+    lenient_age: bool,
+
+    /// Keep leading/trailing whitespace in name and email fields.
+    #[arg(long = "no-trim")]
+    no_trim: bool,
+
+    /// Skip lines whose first non-whitespace character is this one.
+    #[arg(long = "comment-char", value_name = "CHAR")]
+    comment_char: Option<char>,
+
+    /// Fail reading an input that has a line longer than this many bytes (default 1 MiB).
+    #[arg(long = "max-line-len", value_name = "BYTES")]
+    max_line_len: Option<usize>,
+
+    /// Field separator for delimited input: a single character, or `tab` (default `,`).
+    #[arg(long = "delimiter", value_name = "CHAR", value_parser = parse_delimiter)]
+    delimiter: Option<char>,
+
+    /// Input record layout: comma-separated (the default) or fixed-width columns (`--widths`).
+    #[arg(long = "input-format", value_enum, value_name = "FORMAT")]
+    input_format: Option<InputFormat>,
+
+    /// Field widths in characters for `--input-format fixed`, in `--columns` order, e.g.
+    /// `20,3,37`.
+    #[arg(
+        long = "widths",
+        value_name = "WIDTHS",
+        value_delimiter = ',',
+        required_if_eq("input_format", "fixed"),
+        requires = "input_format"
+    )]
+    widths: Vec<usize>,
+
+    /// Positional field mapping, e.g. `email,name,age` (default `name,age,email`).
+    #[arg(long = "columns", value_name = "COLUMNS")]
+    columns: Option<ColumnOrder>,
+
+    /// Treat the first input line as a header row. Unless `--columns` is given, the field
+    /// mapping is inferred from it.
+    #[arg(long = "has-header")]
+    has_header: bool,
+
+    /// Accept records with an empty age field; they skip the age checks and are grouped as `unknown`.
+    #[arg(long = "optional-age")]
+    optional_age: bool,
+
+    /// Reject blank input lines instead of skipping them.
+    #[arg(long = "strict-blank-lines")]
+    strict_blank_lines: bool,
+
+    /// File listing accepted top-level domains, one per line (`#` starts a comment).
+    #[arg(long = "known-tlds", value_name = "FILE")]
+    known_tlds: Option<PathBuf>,
+
+    /// JSON file of age group labels (see `AgeGroupLabels`), e.g. a translation. Labels it
+    /// leaves out stay English.
+    #[arg(long = "labels", value_name = "FILE")]
+    labels: Option<PathBuf>,
+
+    /// Rename a default age bucket, e.g. `50+=senior`. Repeatable.
+    #[arg(long = "age-label", value_name = "BUCKET=LABEL", value_parser = parse_label_override)]
+    age_labels: Vec<(String, String)>,
+
+    /// How usernames are derived: from the name, or as a short hash of the email.
+    #[arg(long = "username-scheme", value_enum)]
+    username_scheme: Option<UsernameScheme>,
+
     /// Age grouping strategy.
     #[arg(long = "age-grouping", value_enum)]
     age_grouping: Option<AgeGroupingMode>,
 
+    /// Group ages by the non-overlapping `min,max,label` ranges in FILE (a JSON array of
+    /// `{"min", "max", "label"}` objects when it ends in `.json`). Ages outside every range
+    /// are grouped as `unspecified`.
+    #[arg(
+        long = "buckets-file",
+        value_name = "FILE",
+        conflicts_with = "age_grouping"
+    )]
+    buckets_file: Option<PathBuf>,
+
+    /// Classify email providers (gmail, outlook, corporate, other) in the output.
+    #[arg(long = "classify-provider")]
+    classify_provider: bool,
+
+    /// How to treat an email address that appears more than once.
+    #[arg(long = "duplicate-emails", value_enum)]
+    duplicate_emails: Option<DuplicateEmailPolicy>,
+
+    /// How to treat ages above the supported maximum: reject the record, or clamp the age
+    /// and warn.
+    #[arg(long = "age-over-range", value_enum, value_name = "POLICY")]
+    age_over_range: Option<AgeOverRangePolicy>,
+
+    /// Email masking for errors and log events.
+    #[arg(long = "log-email-mask", value_enum, value_name = "POLICY")]
+    log_email_mask: Option<EmailMaskPolicy>,
+
+    /// Email masking for `{email}` in output templates.
+    #[arg(long = "output-email-mask", value_enum, value_name = "POLICY")]
+    output_email_mask: Option<EmailMaskPolicy>,
+
+    /// Output template, e.g. "{username}:{age}". Placeholders: {name}, {age}, {age_group},
+    /// {username}, {email}.
+    #[arg(long = "template", value_name = "FORMAT")]
+    template: Option<OutputTemplate>,
+
+    /// Output record format. `csv` writes `name,age,email,username,age_group` rows and `json`
+    /// one JSON object per record; neither can be combined with `--template`.
+    #[arg(long = "format", value_enum, conflicts_with = "template")]
+    format: Option<OutputFormat>,
+
+    /// Never color the output. By default the age group and username are colored when
+    /// writing text output to a terminal and `NO_COLOR` is not set.
+    #[arg(long = "no-color")]
+    no_color: bool,
+
+    /// Collapse repeated whitespace inside names to single spaces.
+    #[arg(long = "collapse-name-whitespace")]
+    collapse_name_whitespace: bool,
+
+    /// Only parse and validate: write each accepted record back as a `name,age,email` row,
+    /// without computing the age group or username.
+    #[arg(long = "no-enrich", conflicts_with_all = ["template", "format"])]
+    no_enrich: bool,
+
+    /// Add a stable hash of the normalised name, age and email to each record, for
+    /// deduplication downstream.
+    #[arg(long = "with-hash", conflicts_with = "no_enrich")]
+    with_hash: bool,
+
+    /// Write each record as a JSON object `{"record":{..},"meta":{..}}`, where `meta` holds the
+    /// config hash, the validation rules applied and a Unix timestamp.
+    #[arg(long = "with-provenance", conflicts_with_all = ["template", "format", "no_enrich"])]
+    with_provenance: bool,
+
+    /// Sort output records by field. Buffers the whole input, so output is not streamed.
+    #[arg(long = "sort-by", value_enum, value_name = "FIELD")]
+    sort_by: Option<SortKey>,
+
+    /// Sort in descending order (with --sort-by).
+    #[arg(long = "sort-desc", requires = "sort_by")]
+    sort_desc: bool,
+
     /// Logging output format.
     #[arg(long = "log", value_enum)]
     log: Option<LoggingMode>,
 
-    /// Hint for parallelism (currently informational only).
+    /// Suppress all tracing output, overriding `--log` and `RUST_LOG`. Errors are still reported.
+    #[arg(long = "quiet", short = 'q')]
+    quiet: bool,
+
+    /// Process at most N non-empty input lines.
+    #[arg(long = "limit", value_name = "N")]
+    limit: Option<usize>,
+
+    /// Print only the number of successfully processed lines, and with `--continue-on-error`
+    /// the number of rejected lines on a second line.
+    #[arg(long = "count")]
+    count: bool,
+
+    /// Print an ASCII chart of the age distribution (10-year bins) to stderr after processing.
+    #[arg(long = "histogram")]
+    histogram: bool,
+
+    /// Print a table of time spent per pipeline stage to stderr after the run.
+    #[arg(long = "profile")]
+    profile: bool,
+
+    /// Skip the run when the inputs and configuration hash to the value stored in `<out>.hash`
+    /// by the last successful run; the new hash is written after each successful run.
+    /// Requires `--out` and file or directory inputs.
+    #[arg(long = "skip-unchanged", requires = "output", conflicts_with = "watch")]
+    skip_unchanged: bool,
+
+    /// Re-run the pipeline whenever the input changes (requires the `watch` feature).
+    #[arg(long = "watch")]
+    watch: bool,
+
+    /// Process stdin line by line as it arrives (e.g. from `tail -f`), writing each record
+    /// immediately. Rejected lines are reported on stderr and skipped without being kept, so
+    /// memory stays flat however long the stream runs. Every record ends with a line ending,
+    /// since that is what flushes it.
+    #[arg(
+        long = "tail",
+        conflicts_with_all = [
+            "watch", "skip_unchanged", "sort_by", "count", "histogram", "has_header", "errors_out",
+            "no_trailing_newline"
+        ]
+    )]
+    tail: bool,
+
+    /// Same as the `schema` subcommand; kept for existing scripts.
+    #[arg(long = "print-schema", hide = true)]
+    print_schema: bool,
+
+    /// Print the resolved configuration as JSON, with where each value came from, and exit
+    /// without reading input.
+    #[arg(long = "explain")]
+    explain: bool,
+
+    /// Check the `--config` file (with any overriding flags) for invalid or inconsistent
+    /// settings, print `OK` or the problem, and exit without reading input.
+    #[arg(
+        long = "validate-config",
+        requires = "config",
+        conflicts_with = "explain"
+    )]
+    validate_config: bool,
+
+    /// Worker threads for reading directory inputs (requires the `rayon` feature; 0 = automatic).
     #[arg(long = "parallel", value_name = "N", default_value_t = 0)]
     parallel: usize,
+
+    /// Only output records with at least this age (others are dropped, not rejected).
+    #[arg(long = "filter-age-min", value_name = "AGE")]
+    filter_age_min: Option<u8>,
+
+    /// Only output records whose email domain is this one, ignoring case (others are dropped,
+    /// not rejected).
+    #[arg(long = "filter-domain", value_name = "DOMAIN")]
+    filter_domain: Option<String>,
+
+    /// Fail with a non-zero exit if the run logs any warning (e.g. an unknown config key, a
+    /// duplicate email or a skipped line). Unsupported files in a directory input and unknown
+    /// `--age-label` keys are caught before any output is written.
+    #[arg(long = "deny-warnings")]
+    deny_warnings: bool,
 }
 
-fn main() {
-    if let Err(err) = try_main() {
-        eprintln!("{err:?}");
-        std::process::exit(1);
+/// Layout of input records, for `--input-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputFormat {
+    Delimited,
+    Fixed,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the pipeline (the default when no subcommand is given).
+    #[command(after_help = EXIT_CODES_HELP)]
+    Run(Box<RunArgs>),
+    /// Print a shell completion script to stdout and exit.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print deterministic synthetic `name,age,email` rows to stdout and exit.
+    Gen {
+        /// Number of rows to generate.
+        #[arg(long = "count", default_value_t = 10)]
+        count: usize,
+        /// Seed for the generator; the same seed always produces the same rows.
+        #[arg(long = "seed", default_value_t = 0)]
+        seed: u64,
+    },
+    /// Print JSON Schemas for the record types and exit (requires the `schema` feature).
+    Schema,
+}
+
+const EXIT_CODES_HELP: &str = "Exit codes:\n  0  every line was processed\n  1  error (with --continue-on-error: every line was rejected)\n  5  partial success: --continue-on-error skipped some lines\n  6  --max-error-rate exceeded\n  130  interrupted by Ctrl-C (`ctrlc` feature); output written so far is flushed";
+
+/// Exit code when `--continue-on-error` skipped some lines but processed others.
+const EXIT_PARTIAL_SUCCESS: u8 = 5;
+
+/// Exit code when more lines than `--max-error-rate` allows were rejected.
+const EXIT_ERROR_RATE: u8 = 6;
+
+/// Exit code when Ctrl-C stopped processing early (128 + SIGINT).
+const EXIT_INTERRUPTED: u8 = 130;
+
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{err:?}");
+            if let Some(hint) = err
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<PipelineError>())
+                .and_then(PipelineError::hint)
+            {
+                eprintln!("hint: {hint}");
+            }
+            let error_rate_exceeded = err.chain().any(|cause| {
+                matches!(
+                    cause.downcast_ref::<PipelineError>(),
+                    Some(PipelineError::ErrorRateExceeded { .. })
+                )
+            });
+            if error_rate_exceeded {
+                ExitCode::from(EXIT_ERROR_RATE)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
     }
 }
 
-fn try_main() -> Result<()> {
-    let cli = Cli::parse();
+fn try_main() -> Result<ExitCode> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
-    let logging_mode = cli.log.unwrap_or_else(default_logging_mode);
-    init_logging(logging_mode).context("failed to initialise logging")?;
+    match cli.command {
+        Some(Command::Run(args)) => {
+            run(&args, matches.subcommand_matches("run").unwrap_or(&matches))
+        }
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "monadic-pipeline",
+                &mut io::stdout(),
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Gen { count, seed }) => {
+            let rows = generate::synthetic_lines(count, seed);
+            write_output(&mut CliSink::Stdout, &rows, &OutputOptions::default())?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Schema) => {
+            print_schema()?;
+            Ok(ExitCode::SUCCESS)
+        }
+        None => run(&cli.run, &matches),
+    }
+}
 
-    if cli.parallel > 1 {
-        warn!(
-            requested = cli.parallel,
-            "parallel flag is currently informational; running sequentially"
-        );
+/// The `run` subcommand; `matches` are the ones `cli` was parsed from, for `--explain`.
+fn run(cli: &RunArgs, matches: &ArgMatches) -> Result<ExitCode> {
+    if !cli.quiet {
+        let logging_mode = cli.log.unwrap_or_else(default_logging_mode);
+        init_logging(logging_mode).context("failed to initialise logging")?;
+    } else if cli.deny_warnings {
+        init_warning_counter().context("failed to initialise logging")?;
+    }
+
+    if cli.print_schema {
+        print_schema()?;
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let mut cfg = ValidationConfig::default();
+    configure_parallelism(cli.parallel)?;
+
+    let (mut cfg, file_config) = match &cli.config {
+        Some(path) => load_config_file(path)?,
+        None => (ValidationConfig::default(), serde_json::Value::Null),
+    };
     if let Some(min_age) = cli.min_age {
         cfg.min_age = min_age;
     }
-    cfg.strict_email = cli.strict_email;
+    if cli.strict_email || cli.strict {
+        cfg.strict_email = true;
+    }
+    if cli.allow_idn {
+        cfg.allow_idn = true;
+    }
+    if cli.classify_provider {
+        cfg.classify_provider = true;
+    }
+    if cli.lenient_age {
+        cfg.parse.lenient_age = true;
+    }
+    if cli.no_trim {
+        cfg.parse.trim_fields = false;
+    }
+    if let Some(marker) = cli.comment_char {
+        cfg.parse.comment_char = Some(marker);
+    }
+    if let Some(max_line_len) = cli.max_line_len {
+        cfg.parse.max_line_len = max_line_len;
+    }
+    if let Some(delimiter) = cli.delimiter {
+        cfg.parse.delimiter = delimiter;
+    }
+    match cli.input_format {
+        Some(InputFormat::Fixed) => cfg.parse.fixed_widths = Some(cli.widths.clone()),
+        Some(InputFormat::Delimited) => cfg.parse.fixed_widths = None,
+        None => {}
+    }
+    if let Some(columns) = cli.columns {
+        cfg.parse.columns = columns;
+    }
+    if cli.strict_blank_lines || cli.strict {
+        cfg.parse.empty_lines = EmptyLinePolicy::Error;
+    }
+    if let Some(policy) = cli.age_over_range {
+        cfg.age_over_range_policy = policy;
+    }
+    if let Some(policy) = cli.duplicate_emails {
+        cfg.duplicate_email_policy = policy;
+    } else if cli.strict {
+        cfg.duplicate_email_policy = DuplicateEmailPolicy::Error;
+    }
+    if let Some(path) = &cli.labels {
+        cfg.labels = load_labels(path)?;
+    }
+    cfg.label_overrides.extend(cli.age_labels.iter().cloned());
+    if let Some(mask) = cli.log_email_mask {
+        cfg.log_email_mask = mask;
+    }
+    if let Some(mask) = cli.output_email_mask {
+        cfg.output_email_mask = mask;
+    }
+    if let Some(path) = &cli.known_tlds {
+        cfg.valid_tlds = Some(load_known_tlds(path)?);
+    }
+    if cli.continue_on_error {
+        cfg.continue_on_error = true;
+    }
+    if let Some(rate) = cli.max_error_rate {
+        cfg.max_error_rate = Some(rate);
+    }
+    if cli.with_line_text {
+        cfg.with_line_text = true;
+    }
+    if cli.profile {
+        cfg.profile = true;
+    }
+    cfg.color = !cli.no_color && cli.output.is_none() && stdout_supports_color();
+    if cli.collapse_name_whitespace {
+        cfg.collapse_name_whitespace = true;
+    }
+    if cli.no_enrich {
+        cfg.skip_enrichment = true;
+    }
+    if cli.optional_age {
+        cfg.require_age = false;
+    }
+    if let Some(template) = &cli.template {
+        cfg.template = Some(template.clone());
+    }
+    if let Some(format) = cli.format {
+        cfg.output_format = format;
+    }
+    if let Some(min_age) = cli.filter_age_min {
+        cfg.filter.min_age = Some(min_age);
+    }
+    if let Some(domain) = &cli.filter_domain {
+        cfg.filter.domain = Some(domain.clone());
+    }
+    if let Some(key) = cli.sort_by {
+        cfg.sort_by = Some(key);
+    }
+    if cli.sort_desc {
+        cfg.sort_desc = true;
+    }
+    if let Some(scheme) = cli.username_scheme {
+        cfg.username_scheme = scheme;
+    }
     if let Some(mode) = cli.age_grouping {
         cfg.age_grouping = mode;
     }
+    if let Some(path) = &cli.buckets_file {
+        cfg.age_grouping = AgeGroupingMode::Custom;
+        cfg.age_buckets = load_age_buckets(path)?;
+    }
 
-    let lines = read_input(&cli.input)?;
-    let line_count = lines.len();
-    info!(lines = line_count, "loaded input lines");
-    let outputs = process_lines(lines, &cfg).context("pipeline execution failed")?;
+    if cli.with_hash {
+        cfg.record_hash = true;
+    }
+    if cli.with_provenance {
+        cfg.provenance = Some(output::Provenance::capture(&cfg));
+    }
 
-    write_output(cli.output.as_deref(), &outputs)?;
+    if cli.explain {
+        explain_config(&cfg, matches, &file_config)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    cfg.validate().context("invalid configuration")?;
+    if cli.validate_config {
+        println!("OK");
+        return Ok(ExitCode::SUCCESS);
+    }
+    if cli.deny_warnings {
+        deny_warnings(cli, &cfg)?;
+    }
+
+    if cli.watch {
+        watch_input(cli, &cfg)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if cli.tail {
+        return tail_stdin(cli, &cfg);
+    }
+
+    let fingerprint = match (cli.skip_unchanged, cli.output.as_deref()) {
+        (true, Some(output)) => {
+            let sidecar = hash_sidecar(output);
+            let fingerprint = input_fingerprint(
+                &CliSource::parse_all(&cli.inputs)?,
+                &cfg,
+                &run_options(cli, None),
+            )?;
+            let stored = fs::read_to_string(&sidecar).unwrap_or_default();
+            if output.exists() && stored.trim() == fingerprint {
+                eprintln!("input unchanged since the last run; skipping");
+                return Ok(ExitCode::SUCCESS);
+            }
+            Some((sidecar, fingerprint))
+        }
+        _ => None,
+    };
+
+    let interrupted = install_interrupt_handler()?;
+    let report = run_once(cli, &cfg, Some(interrupted))?;
+    if report.interrupted {
+        return Ok(ExitCode::from(EXIT_INTERRUPTED));
+    }
+    if cli.deny_warnings {
+        check_warnings_logged()?;
+    }
+    let unreadable = report.parts.iter().any(|part| part.error.is_some());
+    Ok(match report.summary {
+        ProcessSummary { failed: 0, .. } if !unreadable => {
+            if let Some((sidecar, fingerprint)) = fingerprint {
+                fs::write(&sidecar, format!("{fingerprint}\n"))
+                    .with_context(|| format!("failed to write {}", sidecar.display()))?;
+            }
+            ExitCode::SUCCESS
+        }
+        ProcessSummary { processed: 0, .. } => ExitCode::FAILURE,
+        _ => ExitCode::from(EXIT_PARTIAL_SUCCESS),
+    })
+}
+
+/// Whether stdout is a terminal that should get colors, honouring `NO_COLOR` and
+/// `CLICOLOR_FORCE`.
+fn stdout_supports_color() -> bool {
+    anstream::AutoStream::choice(&io::stdout()) != anstream::ColorChoice::Never
+}
+
+/// Where `--skip-unchanged` keeps the fingerprint for `output`: `<output>.hash`.
+fn hash_sidecar(output: &Path) -> PathBuf {
+    let mut sidecar = output.as_os_str().to_owned();
+    sidecar.push(".hash");
+    PathBuf::from(sidecar)
+}
+
+/// `--deny-warnings`, before the run: fail if a warning was already logged (e.g. while loading
+/// the config) or would be for an unsupported file in a directory input or an unknown
+/// `--age-label` key, so no output is written.
+fn deny_warnings(cli: &RunArgs, cfg: &ValidationConfig) -> Result<()> {
+    check_warnings_logged()?;
+    let mut warnings = Vec::new();
+    for source in CliSource::parse_all(&cli.inputs)? {
+        if let CliSource::Directory(path) = source {
+            let (_, unsupported) = partition_directory(&path)?;
+            warnings.extend(
+                unsupported
+                    .iter()
+                    .map(|file| format!("unsupported file {}", file.display())),
+            );
+        }
+    }
+    warnings.extend(
+        cfg.unknown_label_overrides()
+            .into_iter()
+            .map(|key| format!("unknown age group label override `{key}`")),
+    );
+    if !warnings.is_empty() {
+        anyhow::bail!(
+            "warnings denied by --deny-warnings: {}",
+            warnings.join("; ")
+        );
+    }
     Ok(())
 }
 
+/// `--deny-warnings`: fail if any warning has been logged so far.
+fn check_warnings_logged() -> Result<()> {
+    match warnings_logged() {
+        0 => Ok(()),
+        count => anyhow::bail!("{count} warning(s) logged, denied by --deny-warnings"),
+    }
+}
+
+/// Hex SHA-256 over the resolved configuration, the run options that shape the output and the
+/// name and bytes of every input file.
+fn input_fingerprint(
+    sources: &[CliSource],
+    cfg: &ValidationConfig,
+    opts: &RunOptions,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(cfg.canonical_json().to_string().as_bytes());
+    let run_options = serde_json::json!({
+        "limit": opts.limit,
+        "count_only": opts.count_only,
+        "histogram": opts.histogram,
+        "has_header": opts.has_header,
+        "infer_columns": opts.infer_columns,
+        "line_ending": opts.output.line_ending,
+        "trailing_newline": opts.output.trailing_newline,
+    });
+    hasher.update(run_options.to_string().as_bytes());
+    for source in sources {
+        let files = match source {
+            CliSource::File(path) => vec![path.clone()],
+            CliSource::Directory(path) => directory_files(path)?,
+            CliSource::Stdin | CliSource::Url(_) => {
+                anyhow::bail!("--skip-unchanged requires file or directory inputs")
+            }
+        };
+        for file in files {
+            let contents = fs::read(&file)
+                .with_context(|| format!("failed to read input file {}", file.display()))?;
+            hasher.update(file.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update((contents.len() as u64).to_le_bytes());
+            hasher.update(&contents);
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Install a Ctrl-C handler that asks the processing loop to stop after the current line, so
+/// buffered output is still flushed. A second Ctrl-C exits immediately (e.g. while input is
+/// still being read).
+#[cfg(feature = "ctrlc")]
+fn install_interrupt_handler() -> Result<Arc<AtomicBool>> {
+    use std::sync::atomic::Ordering;
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(i32::from(EXIT_INTERRUPTED));
+        }
+    })
+    .context("failed to install Ctrl-C handler")?;
+    Ok(interrupted)
+}
+
+#[cfg(not(feature = "ctrlc"))]
+fn install_interrupt_handler() -> Result<Arc<AtomicBool>> {
+    Ok(Arc::new(AtomicBool::new(false)))
+}
+
+/// `--tail`: stream stdin through the pipeline, flushing every record as it is written.
+fn tail_stdin(cli: &RunArgs, cfg: &ValidationConfig) -> Result<ExitCode> {
+    if cli.inputs.iter().any(|source| source != "-") {
+        anyhow::bail!("--tail reads stdin only; drop --in or pass --in -");
+    }
+    let cfg = ValidationConfig {
+        continue_on_error: true,
+        retain_rejections: false,
+        ..cfg.clone()
+    };
+    // Stdout flushes at every newline unless wrapped in a `BufWriter`.
+    let writer: Box<dyn Write> = match &cli.output {
+        Some(path) => {
+            Box::new(LineWriter::new(File::create(path).with_context(|| {
+                format!("failed to create output file {}", path.display())
+            })?))
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+    let opts = OutputOptions {
+        line_ending: cli.line_ending,
+        trailing_newline: true,
+    };
+
+    let mut read_error = None;
+    let lines = stream_numbered_lines(io::stdin().lock(), &cfg.parse)
+        .map_while(|line| line.map_err(|err| read_error = Some(err)).ok())
+        .take(cli.limit.unwrap_or(usize::MAX));
+    let summary =
+        process_lines_to_writer(lines, &cfg, writer, &opts).context("pipeline execution failed")?;
+    if let Some(err) = read_error {
+        return Err(err).context("failed to read stdin");
+    }
+
+    eprintln!("processed {}, failed {}", summary.processed, summary.failed);
+    if cli.deny_warnings {
+        check_warnings_logged()?;
+    }
+    Ok(match summary {
+        ProcessSummary { failed: 0, .. } => ExitCode::SUCCESS,
+        ProcessSummary { processed: 0, .. } => ExitCode::FAILURE,
+        _ => ExitCode::from(EXIT_PARTIAL_SUCCESS),
+    })
+}
+
+/// The `RunOptions` selected by the `run` flags.
+fn run_options(cli: &RunArgs, stop: Option<Arc<AtomicBool>>) -> RunOptions {
+    RunOptions {
+        output: OutputOptions {
+            line_ending: cli.line_ending,
+            trailing_newline: !cli.no_trailing_newline,
+        },
+        limit: cli.limit,
+        count_only: cli.count,
+        histogram: cli.histogram,
+        has_header: cli.has_header,
+        infer_columns: cli.columns.is_none(),
+        stop,
+    }
+}
+
+fn run_once(
+    cli: &RunArgs,
+    cfg: &ValidationConfig,
+    stop: Option<Arc<AtomicBool>>,
+) -> Result<RunReport> {
+    let sources = CliSource::parse_all(&cli.inputs)?;
+    let mut sink = CliSink::new(cli.output.as_deref());
+    let opts = run_options(cli, stop);
+    let report = run_pipeline(sources.as_slice(), &mut sink, cfg, &opts)?;
+
+    let summary = &report.summary;
+    if let Some(path) = &cli.errors_out {
+        write_error_records(path, summary)?;
+    }
+    if let Some(histogram) = &report.histogram {
+        eprint!("{}", histogram.render(terminal_width()));
+    }
+    if let Some(timings) = summary.timings {
+        eprint!("{}", timings.render());
+    }
+    if report.interrupted {
+        eprintln!(
+            "interrupted: processed {}, failed {}",
+            summary.processed, summary.failed
+        );
+    } else if cfg.continue_on_error {
+        eprintln!("processed {}, failed {}", summary.processed, summary.failed);
+    }
+    if report.parts.len() > 1 || report.parts.iter().any(|part| part.error.is_some()) {
+        for part in &report.parts {
+            match &part.error {
+                Some(err) => eprintln!("  {}: unreadable: {err}", part.name),
+                None => eprintln!(
+                    "  {}: processed {}, failed {}",
+                    part.name, part.processed, part.failed
+                ),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Write the rejections in `summary` to `path` as NDJSON (see `output::error_record`).
+fn write_error_records(path: &Path, summary: &ProcessSummary) -> Result<()> {
+    let records: Vec<String> = summary
+        .rejections
+        .iter()
+        .map(output::error_record)
+        .collect();
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    output::write_lines(BufWriter::new(file), &records, &OutputOptions::default())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(feature = "watch")]
+fn watch_input(cli: &RunArgs, cfg: &ValidationConfig) -> Result<()> {
+    use notify::{RecursiveMode, Watcher};
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tracing::info;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(200);
+    const DEBOUNCE: Duration = Duration::from_millis(250);
+
+    if cli.inputs.iter().any(|source| source == "-") {
+        anyhow::bail!("--watch requires file or directory inputs, not stdin");
+    }
+    if cli.inputs.iter().any(|source| is_url(source)) {
+        anyhow::bail!("--watch requires file or directory inputs, not URLs");
+    }
+
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_flag = Arc::clone(&running);
+    ctrlc::set_handler(move || handler_flag.store(false, Ordering::SeqCst))
+        .context("failed to install Ctrl-C handler")?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .context("failed to create file watcher")?;
+    for source in &cli.inputs {
+        watcher
+            .watch(Path::new(source), RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch {source}"))?;
+    }
+
+    // Writing the output file must not retrigger a run when it lives inside a watched directory.
+    let output = cli
+        .output
+        .as_deref()
+        .and_then(|path| fs::canonicalize(path).ok());
+    let is_relevant = |event: &notify::Event| {
+        !event.kind.is_access()
+            && event
+                .paths
+                .iter()
+                .any(|path| output.as_deref() != fs::canonicalize(path).ok().as_deref())
+    };
+
+    run_watched(cli, cfg);
+    while running.load(Ordering::SeqCst) {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if is_relevant(&event) => {
+                // Collapse bursts of writes (editors often save in several steps) into one run.
+                while rx.recv_timeout(DEBOUNCE).is_ok() {}
+                run_watched(cli, cfg);
+            }
+            Ok(Ok(_)) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Ok(Err(err)) => warn!(error = %err, "file watcher reported an error"),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    info!("watch mode stopped");
+    Ok(())
+}
+
+#[cfg(feature = "watch")]
+fn run_watched(cli: &RunArgs, cfg: &ValidationConfig) {
+    eprintln!("=== run at {} ===", utc_timestamp());
+    // Ctrl-C stops watch mode itself, so each run goes to completion.
+    if let Err(err) = run_once(cli, cfg, None) {
+        eprintln!("{err:?}");
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+fn watch_input(_cli: &RunArgs, _cfg: &ValidationConfig) -> Result<()> {
+    Err(anyhow::anyhow!("watch support is disabled at compile time"))
+}
+
+/// Formats the current time as an RFC 3339 UTC timestamp with second precision.
+#[cfg(feature = "watch")]
+fn utc_timestamp() -> String {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+fn parse_delimiter(raw: &str) -> Result<char, String> {
+    if raw.eq_ignore_ascii_case("tab") || raw == "\\t" {
+        return Ok('\t');
+    }
+    let mut chars = raw.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != '"' => Ok(c),
+        _ => Err(format!(
+            "expected a single character other than `\"` or `tab`, got `{raw}`"
+        )),
+    }
+}
+
+fn parse_label_override(raw: &str) -> Result<(String, String), String> {
+    raw.split_once('=')
+        .map(|(bucket, label)| (bucket.to_owned(), label.to_owned()))
+        .ok_or_else(|| format!("expected BUCKET=LABEL, got `{raw}`"))
+}
+
 fn default_logging_mode() -> LoggingMode {
     if cfg!(feature = "human-logs") {
         LoggingMode::Human
@@ -90,49 +1010,393 @@ fn default_logging_mode() -> LoggingMode {
     }
 }
 
-fn read_input(source: &str) -> Result<Vec<String>> {
-    if source == "-" {
-        read_from_stdin()
-    } else {
-        let path = Path::new(source);
+fn load_labels(path: &Path) -> Result<AgeGroupLabels> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read labels file {}", path.display()))?;
+    serde_json::from_str(&text)
+        .with_context(|| format!("failed to parse labels file {}", path.display()))
+}
+
+/// Read a `--config` file, returning the parsed config and the file's raw JSON.
+fn load_config_file(path: &Path) -> Result<(ValidationConfig, serde_json::Value)> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let cfg = ValidationConfig::from_json(&text)
+        .with_context(|| format!("failed to load config file {}", path.display()))?;
+    let raw = serde_json::from_str(&text).unwrap_or_default();
+    Ok((cfg, raw))
+}
+
+/// Config fields (as dotted JSON paths) and the CLI arguments that can set each of them.
+const CONFIG_FLAGS: &[(&str, &[&str])] = &[
+    ("min_age", &["min_age"]),
+    ("strict_email", &["strict_email", "strict"]),
+    ("age_grouping", &["age_grouping", "buckets_file"]),
+    ("fine_grained_width", &[]),
+    ("age_buckets", &["buckets_file"]),
+    ("classify_provider", &["classify_provider"]),
+    ("supported_age_ceiling", &[]),
+    ("age_over_range_policy", &["age_over_range"]),
+    ("parse.lenient_age", &["lenient_age"]),
+    ("parse.trim_fields", &["no_trim"]),
+    ("parse.comment_char", &["comment_char"]),
+    ("parse.max_line_len", &["max_line_len"]),
+    ("parse.delimiter", &["delimiter"]),
+    ("parse.fixed_widths", &["input_format", "widths"]),
+    ("parse.empty_lines", &["strict_blank_lines", "strict"]),
+    ("parse.columns", &["columns"]),
+    ("duplicate_email_policy", &["duplicate_emails", "strict"]),
+    ("strip_plus_tags", &[]),
+    ("template", &["template"]),
+    ("output_format", &["format"]),
+    ("allow_idn", &["allow_idn"]),
+    ("min_domain_labels", &[]),
+    ("min_tld_len", &[]),
+    ("filter.min_age", &["filter_age_min"]),
+    ("filter.domain", &["filter_domain"]),
+    ("sort_by", &["sort_by"]),
+    ("sort_desc", &["sort_desc"]),
+    ("labels", &["labels"]),
+    ("label_overrides", &["age_labels"]),
+    ("log_email_mask", &["log_email_mask"]),
+    ("output_email_mask", &["output_email_mask"]),
+    ("valid_tlds", &["known_tlds"]),
+    ("continue_on_error", &["continue_on_error"]),
+    ("max_error_rate", &["max_error_rate"]),
+    ("error_rate_min_lines", &[]),
+    ("with_line_text", &["with_line_text"]),
+    ("require_age", &["optional_age"]),
+    ("reject_zero_age", &[]),
+    ("username_scheme", &["username_scheme"]),
+    ("report_every", &[]),
+    ("record_hash", &["with_hash"]),
+    ("profile", &["profile"]),
+    ("color", &["no_color"]),
+    ("collapse_name_whitespace", &["collapse_name_whitespace"]),
+    ("skip_enrichment", &["no_enrich"]),
+];
+
+/// Print `cfg` and, per field, whether it was set on the command line, by the `--config` file
+/// (`file_config`, as written), or left at its default.
+fn explain_config(
+    cfg: &ValidationConfig,
+    matches: &ArgMatches,
+    file_config: &serde_json::Value,
+) -> Result<()> {
+    let sources: serde_json::Map<String, serde_json::Value> = CONFIG_FLAGS
+        .iter()
+        .map(|(field, args)| {
+            let from_cli = args
+                .iter()
+                .any(|arg| matches.value_source(arg) == Some(ValueSource::CommandLine));
+            let in_file = field
+                .split('.')
+                .try_fold(file_config, |value, key| value.get(key))
+                .is_some();
+            let source = match (from_cli, in_file) {
+                (true, _) => "cli",
+                (false, true) => "file",
+                (false, false) => "default",
+            };
+            (field.to_string(), source.into())
+        })
+        .collect();
+    let explained = serde_json::json!({ "config": cfg.canonical_json(), "sources": sources });
+    let rendered =
+        serde_json::to_string_pretty(&explained).context("failed to render configuration")?;
+    write_output(&mut CliSink::Stdout, &[rendered], &OutputOptions::default())
+}
+
+#[cfg(feature = "schema")]
+fn print_schema() -> Result<()> {
+    let schemas = monadic_pipeline::schema::record_schemas();
+    let rendered = serde_json::to_string_pretty(&schemas).context("failed to render schema")?;
+    write_output(&mut CliSink::Stdout, &[rendered], &OutputOptions::default())
+}
+
+#[cfg(not(feature = "schema"))]
+fn print_schema() -> Result<()> {
+    Err(anyhow::anyhow!(
+        "schema support is disabled at compile time"
+    ))
+}
+
+#[cfg(feature = "rayon")]
+fn configure_parallelism(threads: usize) -> Result<()> {
+    if threads > 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("failed to configure worker threads")?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "rayon"))]
+fn configure_parallelism(threads: usize) -> Result<()> {
+    if threads > 1 {
+        warn!(
+            requested = threads,
+            "parallel flag requires the `rayon` feature; running sequentially"
+        );
+    }
+    Ok(())
+}
+
+/// An `--in` argument.
+enum CliSource {
+    Stdin,
+    File(PathBuf),
+    Directory(PathBuf),
+    Url(String),
+}
+
+impl CliSource {
+    fn parse_all(sources: &[String]) -> Result<Vec<Self>> {
+        if sources.iter().filter(|source| *source == "-").count() > 1 {
+            anyhow::bail!("stdin ('-') may be passed to --in at most once");
+        }
+        Ok(sources.iter().map(|source| Self::parse(source)).collect())
+    }
+
+    fn parse(source: &str) -> Self {
+        if source == "-" {
+            return Self::Stdin;
+        }
+        if is_url(source) {
+            return Self::Url(source.to_owned());
+        }
+        let path = PathBuf::from(source);
         if path.is_dir() {
-            read_from_directory(path)
+            Self::Directory(path)
         } else {
-            read_from_file(path)
+            Self::File(path)
         }
     }
 }
 
-fn read_from_stdin() -> Result<Vec<String>> {
-    let stdin = io::stdin();
-    let reader = stdin.lock();
-    let lines: Vec<String> = reader
-        .lines()
-        .collect::<Result<Vec<_>, _>>()
-        .context("failed to read stdin")?;
-    Ok(lines
-        .into_iter()
-        .map(|line| line.trim_end().to_owned())
-        .filter(|line| !line.is_empty())
-        .collect())
+impl InputSource for CliSource {
+    fn read_lines(&self, opts: &ParseOptions) -> Result<Vec<String>> {
+        let lines = self.read_numbered_lines(opts)?;
+        Ok(lines.into_iter().map(|line| line.text).collect())
+    }
+
+    fn read_numbered_lines(&self, opts: &ParseOptions) -> Result<Vec<InputLine>> {
+        match self {
+            Self::Stdin => read_from_stdin(opts),
+            Self::File(path) => read_from_file(path, opts),
+            Self::Directory(path) => read_from_directory(path, opts),
+            Self::Url(url) => read_from_url(url, opts),
+        }
+    }
+
+    fn stream_numbered_lines<'a>(
+        &'a self,
+        opts: &'a ParseOptions,
+    ) -> Result<Box<dyn Iterator<Item = Result<InputLine>> + 'a>> {
+        // Local input is read up front so a malformed line fails the run before any output;
+        // a response body is processed as it downloads.
+        let Self::Url(url) = self else {
+            return Ok(Box::new(
+                self.read_numbered_lines(opts)?.into_iter().map(Ok),
+            ));
+        };
+        let lines = stream_numbered_lines(BufReader::new(open_url(url)?), opts).map(move |line| {
+            line.with_context(|| format!("failed to read response body from {url}"))
+        });
+        Ok(Box::new(lines))
+    }
+
+    fn read_parts(&self, opts: &ParseOptions) -> Vec<InputPart> {
+        let name = match self {
+            Self::Stdin => "-".to_owned(),
+            Self::File(path) => path.display().to_string(),
+            Self::Directory(path) => return read_directory_parts(path, opts),
+            Self::Url(url) => url.clone(),
+        };
+        vec![InputPart {
+            name,
+            lines: self.read_numbered_lines(opts),
+        }]
+    }
+}
+
+/// The `--out` destination.
+enum CliSink {
+    Stdout,
+    File(PathBuf),
+}
+
+impl CliSink {
+    fn new(path: Option<&Path>) -> Self {
+        path.map_or(Self::Stdout, |path| Self::File(path.to_path_buf()))
+    }
+}
+
+impl OutputSink for CliSink {
+    fn open(&mut self) -> Result<Box<dyn Write + '_>> {
+        match self {
+            Self::Stdout => Ok(Box::new(BufWriter::new(io::stdout().lock()))),
+            Self::File(path) => {
+                let file = File::create(&*path)
+                    .with_context(|| format!("failed to create output file {}", path.display()))?;
+                Ok(Box::new(BufWriter::new(file)))
+            }
+        }
+    }
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+fn read_from_url(url: &str, opts: &ParseOptions) -> Result<Vec<InputLine>> {
+    read_numbered_lines(BufReader::new(open_url(url)?), opts)
+        .with_context(|| format!("failed to read response body from {url}"))
+}
+
+/// Fetch `url`, returning its body to be read line by line as it arrives.
+#[cfg(feature = "http")]
+fn open_url(url: &str) -> Result<impl io::Read> {
+    let response = match ureq::get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            anyhow::bail!("GET {url} returned HTTP {code} {}", response.status_text())
+        }
+        Err(err) => return Err(err).with_context(|| format!("failed to fetch {url}")),
+    };
+    if response.status() != 200 {
+        anyhow::bail!(
+            "GET {url} returned HTTP {} {}",
+            response.status(),
+            response.status_text()
+        );
+    }
+    Ok(response.into_reader())
 }
 
-fn read_from_file(path: &Path) -> Result<Vec<String>> {
+#[cfg(not(feature = "http"))]
+fn open_url(_url: &str) -> Result<io::Empty> {
+    Err(anyhow::anyhow!(
+        "HTTP input support is disabled at compile time"
+    ))
+}
+
+fn read_from_stdin(opts: &ParseOptions) -> Result<Vec<InputLine>> {
+    read_numbered_lines(io::stdin().lock(), opts).context("failed to read stdin")
+}
+
+fn read_from_file(path: &Path, opts: &ParseOptions) -> Result<Vec<InputLine>> {
     let file = File::open(path)
         .with_context(|| format!("failed to open input file {}", path.display()))?;
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader
-        .lines()
-        .collect::<Result<Vec<_>, _>>()
-        .with_context(|| format!("failed to read input file {}", path.display()))?;
-    Ok(lines
+    read_numbered_lines(BufReader::new(file), opts)
+        .with_context(|| format!("failed to read input file {}", path.display()))
+}
+
+fn load_known_tlds(path: &Path) -> Result<HashSet<String>> {
+    let opts = ParseOptions {
+        comment_char: Some('#'),
+        ..ParseOptions::default()
+    };
+    let tlds = read_from_file(path, &opts)?
+        .iter()
+        .map(|line| line.text.trim().trim_start_matches('.').to_lowercase())
+        .collect();
+    Ok(tlds)
+}
+
+/// Read `--buckets-file`: JSON for `.json` files, otherwise `min,max,label` lines with an
+/// optional `min,max,label` header and `#` comments.
+fn load_age_buckets(path: &Path) -> Result<Vec<AgeBucket>> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+    {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read buckets file {}", path.display()))?;
+        return serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse buckets file {}", path.display()));
+    }
+    let opts = ParseOptions {
+        comment_char: Some('#'),
+        ..ParseOptions::default()
+    };
+    let mut buckets = Vec::new();
+    for (idx, InputLine { text: line, .. }) in read_from_file(path, &opts)?.iter().enumerate() {
+        let fields: Vec<String> = split_fields(line, ',')?
+            .into_iter()
+            .map(|field| field.value.trim().to_owned())
+            .collect();
+        if idx == 0 && fields == ["min", "max", "label"] {
+            continue;
+        }
+        let context = || {
+            format!(
+                "expected `min,max,label` in buckets file {}, got `{line}`",
+                path.display()
+            )
+        };
+        let [min, max, label] =
+            <[String; 3]>::try_from(fields).map_err(|_| anyhow::anyhow!(context()))?;
+        buckets.push(AgeBucket {
+            min: min.parse().with_context(context)?,
+            max: max.parse().with_context(context)?,
+            label,
+        });
+    }
+    Ok(buckets)
+}
+
+fn read_from_directory(path: &Path, opts: &ParseOptions) -> Result<Vec<InputLine>> {
+    let files = directory_files(path)?;
+    Ok(read_files(&files, opts)
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?
         .into_iter()
-        .map(|line| line.trim_end().to_owned())
-        .filter(|line| !line.is_empty())
+        .flatten()
         .collect())
 }
 
-fn read_from_directory(path: &Path) -> Result<Vec<String>> {
+/// Like `read_from_directory`, but each file is its own part so one unreadable file does not
+/// prevent reading the others.
+fn read_directory_parts(path: &Path, opts: &ParseOptions) -> Vec<InputPart> {
+    let files = match directory_files(path) {
+        Ok(files) => files,
+        Err(err) => {
+            return vec![InputPart {
+                name: path.display().to_string(),
+                lines: Err(err),
+            }]
+        }
+    };
+    files
+        .iter()
+        .zip(read_files(&files, opts))
+        .map(|(file, lines)| InputPart {
+            name: file.display().to_string(),
+            lines,
+        })
+        .collect()
+}
+
+fn file_name_bytes(path: &Path) -> &[u8] {
+    path.file_name().unwrap_or_default().as_encoded_bytes()
+}
+
+/// The supported (`.csv`/`.txt`) files directly inside `path`, in byte order of their file
+/// names (case-sensitive, so `B.csv` sorts before `a.csv`) whatever order the filesystem
+/// lists them in.
+fn directory_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let (supported, unsupported) = partition_directory(path)?;
+    for file in unsupported {
+        warn!(file = %file.display(), "skipping unsupported file");
+    }
+    Ok(supported)
+}
+
+/// The files directly inside `path`, sorted as in `directory_files`, split into supported and
+/// unsupported ones.
+fn partition_directory(path: &Path) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
     let mut files: Vec<PathBuf> = fs::read_dir(path)
         .with_context(|| format!("failed to read directory {}", path.display()))?
         .map(|entry| entry.with_context(|| "failed to access directory entry".to_string()))
@@ -142,41 +1406,44 @@ fn read_from_directory(path: &Path) -> Result<Vec<String>> {
         .filter(|p| p.is_file())
         .collect();
 
-    files.sort();
+    files.sort_by(|a, b| file_name_bytes(a).cmp(file_name_bytes(b)));
 
-    let mut lines = Vec::new();
-    for file in files {
-        match file.extension().and_then(|ext| ext.to_str()) {
-            Some(ext) if matches!(ext.to_ascii_lowercase().as_str(), "csv" | "txt") => {
-                lines.extend(read_from_file(&file)?);
-            }
-            _ => {
-                warn!(file = %file.display(), "skipping unsupported file");
-            }
-        }
-    }
+    Ok(files.into_iter().partition(|file| {
+        file.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| matches!(ext.to_ascii_lowercase().as_str(), "csv" | "txt"))
+    }))
+}
+
+#[cfg(feature = "rayon")]
+fn read_files(files: &[PathBuf], opts: &ParseOptions) -> Vec<Result<Vec<InputLine>>> {
+    use rayon::prelude::*;
 
-    Ok(lines)
+    // Collecting an indexed parallel iterator preserves the (sorted) input order.
+    files
+        .par_iter()
+        .map(|file| read_from_file(file, opts))
+        .collect()
 }
 
-fn write_output(path: Option<&Path>, lines: &[String]) -> Result<()> {
-    match path {
-        Some(path) => {
-            let mut file = File::create(path)
-                .with_context(|| format!("failed to create output file {}", path.display()))?;
-            for line in lines {
-                writeln!(file, "{line}").context("failed to write output line")?;
-            }
-            file.flush().context("failed to flush output file")?;
-            Ok(())
-        }
-        None => {
-            let stdout = io::stdout();
-            let mut handle = stdout.lock();
-            for line in lines {
-                writeln!(handle, "{line}").context("failed to write to stdout")?;
-            }
-            handle.flush().context("failed to flush stdout")
-        }
-    }
+#[cfg(not(feature = "rayon"))]
+fn read_files(files: &[PathBuf], opts: &ParseOptions) -> Vec<Result<Vec<InputLine>>> {
+    files
+        .iter()
+        .map(|file| read_from_file(file, opts))
+        .collect()
+}
+
+/// Columns available for the histogram: `$COLUMNS` when set, otherwise 80.
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+}
+
+/// Write already collected lines to `sink`, e.g. for `gen`, `schema` and `--explain-config`.
+fn write_output(sink: &mut dyn OutputSink, lines: &[String], opts: &OutputOptions) -> Result<()> {
+    output::write_lines(sink.open()?, lines, opts).context("failed to write output")
 }
@@ -0,0 +1,437 @@
+use crate::domain::{EmailMaskPolicy, EnrichedUser, PipelineError, User};
+use crate::mask_email_with;
+use crate::pipeline::RejectedLine;
+use crate::validation::ValidationConfig;
+use anstyle::{AnsiColor, Style};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Shape of each output record.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// `format_user` or the configured template.
+    #[default]
+    Text,
+    /// `name,age,email,username,age_group` rows (plus `record_hash` when enabled), escaped
+    /// with [`csv_field`].
+    Csv,
+    /// One serialised `EnrichedUser` object per line.
+    Json,
+}
+
+/// Column names of `OutputFormat::Csv` rows without a `record_hash`.
+const CSV_HEADER: &str = "name,age,email,username,age_group";
+/// Column names of `OutputFormat::Csv` rows with a `record_hash`.
+const CSV_HEADER_WITH_HASH: &str = "name,age,email,username,age_group,record_hash";
+
+/// Render one enriched record in `format`, without masking, color or a template.
+///
+/// ```
+/// use monadic_pipeline::output::render;
+/// use monadic_pipeline::{enrich_user, parse_line, OutputFormat};
+///
+/// let enriched = enrich_user(parse_line("Alice,30,alice@example.com").unwrap());
+/// assert_eq!(render(&enriched, OutputFormat::Text), "Alice (30, 30s) -> username=alice");
+/// assert_eq!(render(&enriched, OutputFormat::Csv), "Alice,30,alice@example.com,alice,30s");
+/// ```
+pub fn render(enriched: &EnrichedUser, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => render_user(enriched, false),
+        OutputFormat::Csv => csv_row(enriched, EmailMaskPolicy::None),
+        OutputFormat::Json => json_record(enriched, EmailMaskPolicy::None),
+    }
+}
+
+/// The header line to write before records in `format`, if it has one. Only CSV does; pass
+/// `with_hash` when the records carry a `record_hash` (see `ValidationConfig::record_hash`) so
+/// the header names that column too.
+///
+/// ```
+/// use monadic_pipeline::output::render_header;
+/// use monadic_pipeline::OutputFormat;
+///
+/// assert_eq!(render_header(OutputFormat::Csv, false), Some("name,age,email,username,age_group"));
+/// assert_eq!(render_header(OutputFormat::Json, true), None);
+/// ```
+pub fn render_header(format: OutputFormat, with_hash: bool) -> Option<&'static str> {
+    match format {
+        OutputFormat::Csv if with_hash => Some(CSV_HEADER_WITH_HASH),
+        OutputFormat::Csv => Some(CSV_HEADER),
+        OutputFormat::Text | OutputFormat::Json => None,
+    }
+}
+
+/// Encode a single CSV field: values containing `,`, `"`, `\r` or `\n` are wrapped in quotes
+/// with internal quotes doubled; anything else is returned unchanged.
+///
+/// The result reads back losslessly with `parse_line` and `parsing::split_fields`:
+///
+/// ```
+/// use monadic_pipeline::output::csv_field;
+/// use monadic_pipeline::parse_line;
+///
+/// assert_eq!(csv_field("alice"), "alice");
+/// assert_eq!(csv_field(r#"Doe, "JJ" Jane"#), r#""Doe, ""JJ"" Jane""#);
+///
+/// let line = format!("{},30,jj@example.com", csv_field(r#"Doe, "JJ" Jane"#));
+/// assert_eq!(parse_line(&line).unwrap().name, r#"Doe, "JJ" Jane"#);
+/// ```
+pub fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Render an enriched record as a CSV row (see `OutputFormat::Csv`), masking the email
+/// according to `email_mask`. An absent age is written as an empty field, and a
+/// `record_hash`, when present, is appended as a sixth column.
+pub fn csv_row(enriched: &EnrichedUser, email_mask: EmailMaskPolicy) -> String {
+    let age = enriched
+        .user
+        .age
+        .map(|age| age.to_string())
+        .unwrap_or_default();
+    let email = mask_email_with(&enriched.user.email, email_mask);
+    let mut row = [
+        enriched.user.name.as_str(),
+        age.as_str(),
+        email.as_str(),
+        enriched.username.as_str(),
+        enriched.age_group.label(),
+    ]
+    .map(csv_field)
+    .join(",");
+    if let Some(hash) = &enriched.record_hash {
+        row.push(',');
+        row.push_str(hash);
+    }
+    row
+}
+
+/// Render a validated, unenriched user as a `name,age,email` CSV row, masking the email
+/// according to `email_mask`. This is the output of `ValidationConfig::skip_enrichment`.
+///
+/// ```
+/// use monadic_pipeline::output::user_row;
+/// use monadic_pipeline::{parse_line, EmailMaskPolicy};
+///
+/// let user = parse_line(r#""Doe, Jane",30,jane@example.com"#).unwrap();
+/// assert_eq!(user_row(&user, EmailMaskPolicy::None), r#""Doe, Jane",30,jane@example.com"#);
+/// ```
+pub fn user_row(user: &User, email_mask: EmailMaskPolicy) -> String {
+    let age = user.age.map(|age| age.to_string()).unwrap_or_default();
+    let email = mask_email_with(&user.email, email_mask);
+    [user.name.as_str(), age.as_str(), email.as_str()]
+        .map(csv_field)
+        .join(",")
+}
+
+/// Render a rejected line as a single-line JSON object: its 1-based input `line` number, the
+/// `error` message, the serialised `PipelineError` (its `kind` and fields) and, when kept, the
+/// masked `line_text`.
+///
+/// ```
+/// use monadic_pipeline::output::error_record;
+/// use monadic_pipeline::{PipelineError, RejectedLine};
+///
+/// let rejected = RejectedLine {
+///     line: 2,
+///     error: PipelineError::InvalidAge { age: 5, min_age: 18 },
+///     text: None,
+/// };
+/// assert_eq!(
+///     error_record(&rejected),
+///     r#"{"line":2,"error":"age 5 is below configured minimum 18","kind":"InvalidAge","age":5,"min_age":18}"#
+/// );
+/// ```
+pub fn error_record(rejected: &RejectedLine) -> String {
+    #[derive(Serialize)]
+    struct ErrorRecord<'a> {
+        line: usize,
+        error: String,
+        #[serde(flatten)]
+        detail: &'a PipelineError,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        line_text: Option<&'a str>,
+    }
+
+    serde_json::to_string(&ErrorRecord {
+        line: rejected.line,
+        error: rejected.error.to_string(),
+        detail: &rejected.error,
+        line_text: rejected.text.as_deref(),
+    })
+    .expect("pipeline errors serialise")
+}
+
+/// Where an output record came from, attached to each record by `ValidationConfig::provenance`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Hex SHA-256 of `ValidationConfig::canonical_json`.
+    pub config_hash: String,
+    /// Names of the validation rules every record passed, in the order they ran.
+    pub rules: Vec<String>,
+    /// When the provenance was captured, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Provenance {
+    /// Snapshot `cfg` and its rules, timestamped now.
+    pub fn capture(cfg: &ValidationConfig) -> Self {
+        let digest = Sha256::digest(cfg.canonical_json().to_string().as_bytes());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self {
+            config_hash: digest.iter().map(|byte| format!("{byte:02x}")).collect(),
+            rules: cfg
+                .rules()
+                .iter()
+                .map(|rule| rule.name().to_owned())
+                .collect(),
+            timestamp,
+        }
+    }
+}
+
+/// Render an enriched record as a single-line JSON object (see `OutputFormat::Json`), masking
+/// the email according to `email_mask`.
+pub fn json_record(enriched: &EnrichedUser, email_mask: EmailMaskPolicy) -> String {
+    serde_json::to_string(&masked_record(enriched, email_mask)).expect("records serialise")
+}
+
+/// `enriched`, with its email masked according to `mask` when that changes anything.
+fn masked_record(enriched: &EnrichedUser, mask: EmailMaskPolicy) -> Cow<'_, EnrichedUser> {
+    if mask == EmailMaskPolicy::None {
+        Cow::Borrowed(enriched)
+    } else {
+        let mut masked = enriched.clone();
+        masked.user.email = mask_email_with(&enriched.user.email, mask);
+        Cow::Owned(masked)
+    }
+}
+
+/// Render `enriched` as a JSON object of the form `{"record":{..},"meta":{..}}`, masking the
+/// email with `mask`.
+///
+/// ```
+/// use monadic_pipeline::output::{provenance_record, Provenance};
+/// use monadic_pipeline::{enrich_user, parse_line, EmailMaskPolicy, ValidationConfig};
+///
+/// let meta = Provenance::capture(&ValidationConfig::default());
+/// let enriched = enrich_user(parse_line("Alice,30,alice@example.com").unwrap());
+/// let record: serde_json::Value =
+///     serde_json::from_str(&provenance_record(&enriched, EmailMaskPolicy::None, &meta)).unwrap();
+/// assert_eq!(record["record"]["username"], "alice");
+/// assert_eq!(record["meta"]["rules"][0], "NonEmptyName");
+/// ```
+pub fn provenance_record(
+    enriched: &EnrichedUser,
+    mask: EmailMaskPolicy,
+    meta: &Provenance,
+) -> String {
+    #[derive(Serialize)]
+    struct ProvenanceRecord<'a> {
+        record: Cow<'a, EnrichedUser>,
+        meta: &'a Provenance,
+    }
+
+    let record = masked_record(enriched, mask);
+    serde_json::to_string(&ProvenanceRecord { record, meta }).expect("records serialise")
+}
+
+/// Style of the age group in colored `format_user` output.
+const AGE_GROUP_STYLE: Style = AnsiColor::Cyan.on_default();
+/// Style of the username in colored `format_user` output.
+const USERNAME_STYLE: Style = AnsiColor::Green.on_default().bold();
+
+/// Like `format_user`, with the age group and username highlighted by ANSI escape codes.
+///
+/// ```
+/// use monadic_pipeline::output::format_user_colored;
+/// use monadic_pipeline::{enrich_user, parse_line};
+///
+/// let enriched = enrich_user(parse_line("Alice,30,alice@example.com").unwrap());
+/// assert_eq!(
+///     format_user_colored(&enriched),
+///     "Alice (30, \x1b[36m30s\x1b[0m) -> username=\x1b[1m\x1b[32malice\x1b[0m"
+/// );
+/// ```
+pub fn format_user_colored(enriched: &EnrichedUser) -> String {
+    render_user(enriched, true)
+}
+
+/// `format_user`, optionally colored. The plain rendering contains no escape codes at all.
+pub(crate) fn render_user(enriched: &EnrichedUser, color: bool) -> String {
+    let styled = |style: Style, text: &str| {
+        if color {
+            format!("{}{text}{}", style.render(), style.render_reset())
+        } else {
+            text.to_owned()
+        }
+    };
+    let age = enriched
+        .user
+        .age
+        .map_or_else(|| "?".to_string(), |age| age.to_string());
+    let mut formatted = format!(
+        "{} ({}, {}) -> username={}",
+        enriched.user.name,
+        age,
+        styled(AGE_GROUP_STYLE, enriched.age_group.label()),
+        styled(USERNAME_STYLE, &enriched.username)
+    );
+    if let Some(provider) = enriched.provider {
+        formatted.push_str(&format!(" provider={provider}"));
+    }
+    if let Some(hash) = &enriched.record_hash {
+        formatted.push_str(&format!(" hash={hash}"));
+    }
+    formatted
+}
+
+/// Line terminator used when writing output records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+        }
+    }
+}
+
+/// How output records are separated when written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputOptions {
+    pub line_ending: LineEnding,
+    /// Terminate the last record too; when false the ending is only written between records.
+    pub trailing_newline: bool,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+        }
+    }
+}
+
+/// Somewhere output records go: stdout, a file, a response body, ...
+pub trait OutputSink {
+    /// Open the destination for writing; callers flush (via `RecordWriter::finish` or
+    /// [`write_lines`]) before dropping the writer.
+    fn open(&mut self) -> anyhow::Result<Box<dyn Write + '_>>;
+}
+
+/// In-memory `OutputSink` that accumulates everything written to it.
+///
+/// ```
+/// use monadic_pipeline::output::{write_lines, MemorySink, OutputOptions, OutputSink};
+///
+/// let mut sink = MemorySink::new();
+/// write_lines(sink.open().unwrap(), &["a", "b"], &OutputOptions::default()).unwrap();
+/// assert_eq!(sink.contents(), b"a\nb\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    bytes: Vec<u8>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn open(&mut self) -> anyhow::Result<Box<dyn Write + '_>> {
+        Ok(Box::new(&mut self.bytes))
+    }
+}
+
+/// Records written between explicit flushes by `RecordWriter`.
+const FLUSH_INTERVAL: usize = 1024;
+
+/// Incremental writer that applies `OutputOptions` one record at a time.
+///
+/// Line endings are written along with each record (between records only when
+/// `trailing_newline` is off); call [`finish`](Self::finish) after the last one to flush.
+pub struct RecordWriter<W: Write> {
+    writer: W,
+    opts: OutputOptions,
+    written: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, opts: OutputOptions) -> Self {
+        Self {
+            writer,
+            opts,
+            written: 0,
+        }
+    }
+
+    pub fn write_record(&mut self, record: &str) -> io::Result<()> {
+        let ending = self.opts.line_ending.as_str();
+        if self.written > 0 && !self.opts.trailing_newline {
+            self.writer.write_all(ending.as_bytes())?;
+        }
+        self.writer.write_all(record.as_bytes())?;
+        if self.opts.trailing_newline {
+            self.writer.write_all(ending.as_bytes())?;
+        }
+        self.written += 1;
+        if self.written.is_multiple_of(FLUSH_INTERVAL) {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flush the underlying writer and return the number of records written.
+    pub fn finish(mut self) -> io::Result<usize> {
+        self.writer.flush()?;
+        Ok(self.written)
+    }
+}
+
+/// Write `lines` to `writer` using the configured line ending, then flush.
+///
+/// ```
+/// use monadic_pipeline::output::{write_lines, LineEnding, OutputOptions};
+///
+/// let opts = OutputOptions { line_ending: LineEnding::Crlf, trailing_newline: false };
+/// let mut out = Vec::new();
+/// write_lines(&mut out, &["a", "b"], &opts).unwrap();
+/// assert_eq!(out, b"a\r\nb");
+/// ```
+pub fn write_lines<W, S>(writer: W, lines: &[S], opts: &OutputOptions) -> io::Result<()>
+where
+    W: Write,
+    S: AsRef<str>,
+{
+    let mut records = RecordWriter::new(writer, *opts);
+    for line in lines {
+        records.write_record(line.as_ref())?;
+    }
+    records.finish().map(|_| ())
+}
@@ -0,0 +1,417 @@
+use crate::domain::{EmptyLinePolicy, PipelineError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Options controlling how raw lines are turned into `User` records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseOptions {
+    /// Accept ages such as `+30`, `3_0` or `30.0` in addition to plain integers.
+    #[serde(default)]
+    pub lenient_age: bool,
+    /// Strip surrounding whitespace from the name and email fields (and the validated name).
+    /// The age field is always trimmed before numeric parsing.
+    #[serde(default = "default_trim_fields")]
+    pub trim_fields: bool,
+    /// Lines starting with this character (after optional whitespace) are skipped by readers.
+    #[serde(default)]
+    pub comment_char: Option<char>,
+    /// Whether readers drop blank lines or keep them so the pipeline rejects them.
+    #[serde(default)]
+    pub empty_lines: EmptyLinePolicy,
+    /// Which field each position of a line holds.
+    #[serde(default)]
+    pub columns: ColumnOrder,
+    /// Longest line, in bytes and excluding the line terminator, that readers accept.
+    #[serde(default = "default_max_line_len")]
+    pub max_line_len: usize,
+    /// Character separating the fields of a line, e.g. `\t` for TSV. Quoted fields may contain
+    /// it whatever it is.
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// Read fixed-width records with these field widths, in characters and in `columns`
+    /// order, instead of comma-separated fields (see `split_fixed_width`).
+    #[serde(default)]
+    pub fixed_widths: Option<Vec<usize>>,
+}
+
+impl ParseOptions {
+    /// Whether `line` is a comment according to `comment_char`.
+    pub fn is_comment(&self, line: &str) -> bool {
+        self.comment_char
+            .is_some_and(|marker| line.trim_start().starts_with(marker))
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            lenient_age: false,
+            trim_fields: default_trim_fields(),
+            comment_char: None,
+            empty_lines: EmptyLinePolicy::default(),
+            columns: ColumnOrder::default(),
+            max_line_len: default_max_line_len(),
+            delimiter: default_delimiter(),
+            fixed_widths: None,
+        }
+    }
+}
+
+fn default_trim_fields() -> bool {
+    true
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+/// 1 MiB: far beyond any real record, small enough to bound memory per line.
+fn default_max_line_len() -> usize {
+    1024 * 1024
+}
+
+/// A field of an input record.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Name,
+    Age,
+    Email,
+}
+
+impl Column {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Age => "age",
+            Self::Email => "email",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "name" => Some(Self::Name),
+            "age" => Some(Self::Age),
+            "email" => Some(Self::Email),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Positional mapping of input fields, written like a header row (`email,name,age`).
+///
+/// Each of `name`, `age` and `email` must appear exactly once; names are case-insensitive and
+/// may be quoted, so a CSV header row parses directly.
+///
+/// ```
+/// use monadic_pipeline::parsing::{Column, ColumnOrder};
+///
+/// let order: ColumnOrder = "Email, Name, Age".parse().unwrap();
+/// assert_eq!(order.columns(), [Column::Email, Column::Name, Column::Age]);
+/// assert_eq!(order.to_string(), "email,name,age");
+///
+/// assert!("name,age".parse::<ColumnOrder>().is_err());
+/// assert!("name,name,email".parse::<ColumnOrder>().is_err());
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ColumnOrder([Column; 3]);
+
+impl ColumnOrder {
+    pub fn columns(&self) -> [Column; 3] {
+        self.0
+    }
+
+    /// Parse a header row whose column names are separated by `delimiter`.
+    ///
+    /// ```
+    /// use monadic_pipeline::parsing::{Column, ColumnOrder};
+    ///
+    /// let order = ColumnOrder::from_header("email\tname\tage", '\t').unwrap();
+    /// assert_eq!(order.columns()[0], Column::Email);
+    /// ```
+    pub fn from_header(header: &str, delimiter: char) -> Result<Self, PipelineError> {
+        Self::from_names(&split_fields(header, delimiter)?)
+    }
+
+    fn from_names(names: &[Field]) -> Result<Self, PipelineError> {
+        let invalid = |reason: String| PipelineError::InvalidConfig { reason };
+        if names.len() != 3 {
+            return Err(invalid(format!(
+                "expected 3 columns (name, age, email), got {}",
+                names.len()
+            )));
+        }
+
+        let mut columns = Vec::with_capacity(3);
+        for field in names {
+            let name = field.value.trim();
+            let column = Column::from_name(name)
+                .ok_or_else(|| invalid(format!("unknown column `{name}`")))?;
+            if columns.contains(&column) {
+                return Err(invalid(format!("column `{column}` listed more than once")));
+            }
+            columns.push(column);
+        }
+        Ok(Self([columns[0], columns[1], columns[2]]))
+    }
+}
+
+impl Default for ColumnOrder {
+    fn default() -> Self {
+        Self([Column::Name, Column::Age, Column::Email])
+    }
+}
+
+impl FromStr for ColumnOrder {
+    type Err = PipelineError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        Self::from_header(spec, ',')
+    }
+}
+
+impl TryFrom<String> for ColumnOrder {
+    type Error = PipelineError;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        spec.parse()
+    }
+}
+
+impl From<ColumnOrder> for String {
+    fn from(order: ColumnOrder) -> Self {
+        order.to_string()
+    }
+}
+
+impl fmt::Display for ColumnOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [first, second, third] = self.0;
+        write!(f, "{first},{second},{third}")
+    }
+}
+
+/// A single field extracted from a delimited line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Field contents with surrounding quotes removed and `""` unescaped.
+    pub value: String,
+    /// 1-based character column where the field starts.
+    pub column: usize,
+}
+
+/// Split a delimited line into fields, honouring double-quoted values.
+///
+/// Quoted fields may contain the delimiter and escape a literal quote by doubling it (`""`).
+/// Any delimiter works the same way, including whitespace such as `\t`. Malformed quoting is
+/// reported as `PipelineError::Parse` with the offending column.
+///
+/// ```
+/// use monadic_pipeline::parsing::split_fields;
+///
+/// let fields = split_fields(r#""Doe, Jane",30,jane@example.com"#, ',').unwrap();
+/// assert_eq!(fields[0].value, "Doe, Jane");
+/// assert_eq!(fields[1].column, 13);
+///
+/// let fields = split_fields("\"Doe\tJane\"\t30\tjane@example.com", '\t').unwrap();
+/// assert_eq!(fields[0].value, "Doe\tJane");
+/// ```
+pub fn split_fields(line: &str, delimiter: char) -> Result<Vec<Field>, PipelineError> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().enumerate().peekable();
+
+    loop {
+        let start = chars
+            .peek()
+            .map_or_else(|| line.chars().count(), |(idx, _)| *idx)
+            + 1;
+        let mut value = String::new();
+
+        while let Some(&(_, c)) = chars.peek() {
+            if c.is_whitespace() && c != delimiter {
+                value.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if let Some(&(idx, '"')) = chars.peek() {
+            chars.next();
+            value.clear();
+            let quote_column = idx + 1;
+            loop {
+                match chars.next() {
+                    None => {
+                        return Err(malformed("unterminated quote".into(), quote_column));
+                    }
+                    Some((_, '"')) if matches!(chars.peek(), Some((_, '"'))) => {
+                        chars.next();
+                        value.push('"');
+                    }
+                    Some((_, '"')) => break,
+                    Some((_, c)) => value.push(c),
+                }
+            }
+
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_whitespace() && c != delimiter {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            match chars.next() {
+                None => {
+                    fields.push(Field {
+                        value,
+                        column: start,
+                    });
+                    return Ok(fields);
+                }
+                Some((_, c)) if c == delimiter => fields.push(Field {
+                    value,
+                    column: start,
+                }),
+                Some((idx, c)) => {
+                    return Err(malformed(
+                        format!("unexpected `{c}` after closing quote"),
+                        idx + 1,
+                    ));
+                }
+            }
+            continue;
+        }
+
+        loop {
+            match chars.next() {
+                None => {
+                    fields.push(Field {
+                        value,
+                        column: start,
+                    });
+                    return Ok(fields);
+                }
+                Some((_, c)) if c == delimiter => {
+                    fields.push(Field {
+                        value,
+                        column: start,
+                    });
+                    break;
+                }
+                Some((idx, '"')) => {
+                    return Err(malformed(
+                        "unexpected quote in unquoted field".into(),
+                        idx + 1,
+                    ));
+                }
+                Some((_, c)) => value.push(c),
+            }
+        }
+    }
+}
+
+/// Split a fixed-width line into fields of the given widths (in characters), trimming the
+/// padding from each value.
+///
+/// The last field may be cut short, since readers trim trailing whitespace, but a line that
+/// ends before it starts is rejected. Anything other than whitespace past the last field
+/// becomes an extra field.
+///
+/// ```
+/// use monadic_pipeline::parsing::split_fixed_width;
+///
+/// let fields = split_fixed_width("Alice     30 alice@example.com", &[10, 3, 17]).unwrap();
+/// assert_eq!(fields[0].value, "Alice");
+/// assert_eq!(fields[1].value, "30");
+/// assert_eq!(fields[2].column, 14);
+/// assert_eq!(split_fixed_width("Bob       45 bob@example.com", &[10, 3, 17]).unwrap().len(), 3);
+/// assert!(split_fixed_width("Alice     30", &[10, 3, 17]).is_err());
+/// ```
+pub fn split_fixed_width(line: &str, widths: &[usize]) -> Result<Vec<Field>, PipelineError> {
+    let chars: Vec<char> = line.chars().collect();
+    let last_start: usize = widths.iter().rev().skip(1).sum();
+    let expected = last_start + 1;
+    if chars.len() < expected {
+        return Err(malformed(
+            format!(
+                "line is {} characters, expected at least {expected}",
+                chars.len()
+            ),
+            chars.len() + 1,
+        ));
+    }
+
+    let mut fields = Vec::with_capacity(widths.len() + 1);
+    let mut start = 0;
+    for &width in widths {
+        let end = (start + width).min(chars.len());
+        fields.push(Field {
+            value: chars[start..end]
+                .iter()
+                .collect::<String>()
+                .trim()
+                .to_owned(),
+            column: start + 1,
+        });
+        start = end;
+    }
+    let rest: String = chars[start..].iter().collect();
+    if !rest.trim().is_empty() {
+        fields.push(Field {
+            value: rest.trim().to_owned(),
+            column: start + 1,
+        });
+    }
+    Ok(fields)
+}
+
+fn malformed(reason: String, column: usize) -> PipelineError {
+    PipelineError::Parse {
+        reason,
+        column: Some(column),
+    }
+}
+
+/// Parse an age tolerantly: surrounding whitespace, a leading `+`, single underscores between
+/// digits (`3_0`) and an all-zero fractional part (`30.0`) are accepted; anything else
+/// non-numeric or out of range is not.
+///
+/// ```
+/// use monadic_pipeline::parsing::parse_age_lenient;
+///
+/// assert_eq!(parse_age_lenient(" +30.0 "), Some(30));
+/// assert_eq!(parse_age_lenient("1_05"), Some(105));
+/// assert_eq!(parse_age_lenient("30.5"), None);
+/// ```
+pub fn parse_age_lenient(raw: &str) -> Option<u8> {
+    let trimmed = raw.trim();
+    let unsigned = trimmed.strip_prefix('+').unwrap_or(trimmed);
+    let integer = match unsigned.split_once('.') {
+        Some((integer, fraction))
+            if !fraction.is_empty() && fraction.bytes().all(|b| b == b'0') =>
+        {
+            integer
+        }
+        Some(_) => return None,
+        None => unsigned,
+    };
+
+    let mut digits = String::with_capacity(integer.len());
+    for group in integer.split('_') {
+        if group.is_empty() || !group.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.push_str(group);
+    }
+    digits.parse().ok()
+}
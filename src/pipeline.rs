@@ -1,15 +1,199 @@
-use crate::domain::PipelineError;
+use crate::domain::{
+    AgeGroupingMode, DuplicateEmailPolicy, EmailMaskPolicy, EmptyLinePolicy, EnrichedUser,
+    PipelineError, SortKey, User,
+};
+use crate::input::InputLine;
+use crate::output::{OutputOptions, RecordWriter};
+use crate::stats::StageTimings;
 use crate::validation::ValidationConfig;
-use crate::{enrich_user_with_mode, format_user, parse_line, validate_user};
-use tracing::{error, info, instrument};
+use crate::{
+    enrich_user_with_config, enrich_user_with_mode, format_with_config, mask_email_with,
+    parse_line_with, strip_plus_tag, validate_user,
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, instrument, warn};
+
+pub mod builder;
+
+pub use builder::Pipeline;
+
+/// First half of the pipeline: parse a line and validate the resulting user.
+///
+/// Together with [`enrich`] this lets callers observe or adjust the intermediate `User`
+/// before enrichment:
+///
+/// ```
+/// use monadic_pipeline::{enrich, format_user, parse_and_validate, ValidationConfig};
+///
+/// let cfg = ValidationConfig::default();
+/// let mut user = parse_and_validate("Alice,30,alice@example.com", &cfg).unwrap();
+/// user.name.make_ascii_uppercase();
+/// let enriched = enrich(user, cfg.age_grouping);
+/// assert_eq!(format_user(&enriched), "ALICE (30, 30s) -> username=alice");
+/// ```
+pub fn parse_and_validate(line: &str, cfg: &ValidationConfig) -> Result<User, PipelineError> {
+    parse_line_with(line, &cfg.parse).and_then(|user| validate_user(user, cfg))
+}
+
+/// Second half of the pipeline: derive age group, username and initials for a validated user.
+pub fn enrich(user: User, mode: AgeGroupingMode) -> EnrichedUser {
+    enrich_user_with_mode(user, mode)
+}
 
 /// Runs the full pipeline against a single line of input.
 #[instrument(name = "process_line", level = "debug", skip(line, cfg), fields(line_len = line.len()))]
 pub fn process_line(line: &str, cfg: &ValidationConfig) -> Result<String, PipelineError> {
-    parse_line(line)
-        .and_then(|user| validate_user(user, cfg))
-        .map(|user| enrich_user_with_mode(user, cfg.age_grouping))
-        .map(|enriched| format_user(&enriched))
+    run_stages(line, cfg).map(|enriched| format_with_config(&enriched, cfg))
+}
+
+/// Same as `process_line`, but tags the span and its events with the 1-based input row.
+/// Blank-line errors name `source_line`, the line's number in its input.
+#[instrument(name = "process_line", level = "debug", skip(line, cfg, emails), fields(line_len = line.len()))]
+fn process_numbered_line(
+    line_no: usize,
+    source_line: usize,
+    line: &str,
+    cfg: &ValidationConfig,
+    emails: &mut EmailTracker,
+    timings: Option<&mut StageTimings>,
+) -> Result<EnrichedUser, PipelineError> {
+    let result = if cfg.parse.empty_lines == EmptyLinePolicy::Error && line.trim().is_empty() {
+        Err(PipelineError::Parse {
+            reason: format!("line {source_line} is blank"),
+            column: None,
+        })
+    } else {
+        run_stages_timed(line, cfg, timings)
+            .and_then(|enriched| emails.check(&enriched.user).map(|_| enriched))
+    };
+    match &result {
+        Ok(_) => debug!(line_no, "line processed"),
+        Err(err) => debug!(line_no, error = %err, "line rejected"),
+    }
+    result
+}
+
+fn run_stages(line: &str, cfg: &ValidationConfig) -> Result<EnrichedUser, PipelineError> {
+    parse_and_validate(line, cfg).map(|user| enrich_user_with_config(user, cfg))
+}
+
+/// `run_stages`, adding the time spent in each stage to `timings` when given.
+fn run_stages_timed(
+    line: &str,
+    cfg: &ValidationConfig,
+    timings: Option<&mut StageTimings>,
+) -> Result<EnrichedUser, PipelineError> {
+    let Some(timings) = timings else {
+        return run_stages(line, cfg);
+    };
+    let user = timed(&mut timings.parse, || parse_line_with(line, &cfg.parse))?;
+    let user = timed(&mut timings.validate, || validate_user(user, cfg))?;
+    Ok(timed(&mut timings.enrich, || {
+        enrich_user_with_config(user, cfg)
+    }))
+}
+
+fn timed<T>(spent: &mut Duration, stage: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = stage();
+    *spent += start.elapsed();
+    result
+}
+
+/// Formats a record, adding the elapsed time to `spent` when `cfg.profile` is set.
+fn format_record(enriched: &EnrichedUser, cfg: &ValidationConfig, spent: &mut Duration) -> String {
+    if cfg.profile {
+        timed(spent, || format_with_config(enriched, cfg))
+    } else {
+        format_with_config(enriched, cfg)
+    }
+}
+
+/// Anything shaped like an email address inside free text.
+static EMAIL_IN_TEXT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"[^\s,;"@]+@[^\s,;"]*"#).expect("valid email-in-text regex"));
+
+/// Mask every email-like token of `text` according to `policy`.
+fn mask_emails_in(text: &str, policy: EmailMaskPolicy) -> String {
+    EMAIL_IN_TEXT
+        .replace_all(text, |caps: &regex::Captures<'_>| {
+            mask_email_with(&caps[0], policy)
+        })
+        .into_owned()
+}
+
+/// The key `DuplicateEmailPolicy` compares users by: the email address trimmed and
+/// lowercased, so callers deduplicating on their own match the pipeline.
+///
+/// ```
+/// use monadic_pipeline::{dedup_key, User};
+///
+/// let user = User { name: "Alice".into(), age: Some(30), email: " Alice@Example.COM ".into() };
+/// assert_eq!(dedup_key(&user), "alice@example.com");
+/// ```
+pub fn dedup_key(user: &User) -> String {
+    dedup_key_with(user, false)
+}
+
+/// Like [`dedup_key`], optionally dropping a `+tag` from the local part first (see
+/// `ValidationConfig::strip_plus_tags`).
+///
+/// ```
+/// use monadic_pipeline::{dedup_key_with, User};
+///
+/// let user = User { name: "Alice".into(), age: Some(30), email: "alice+news@example.com".into() };
+/// assert_eq!(dedup_key_with(&user, true), "alice@example.com");
+/// assert_eq!(dedup_key_with(&user, false), "alice+news@example.com");
+/// ```
+pub fn dedup_key_with(user: &User, strip_plus_tags: bool) -> String {
+    let email = user.email.trim();
+    if strip_plus_tags {
+        strip_plus_tag(email).to_lowercase()
+    } else {
+        email.to_lowercase()
+    }
+}
+
+/// Cross-line state used to enforce `DuplicateEmailPolicy`.
+struct EmailTracker {
+    policy: DuplicateEmailPolicy,
+    mask: EmailMaskPolicy,
+    strip_plus_tags: bool,
+    seen: HashSet<String>,
+}
+
+impl EmailTracker {
+    fn new(cfg: &ValidationConfig) -> Self {
+        Self {
+            policy: cfg.duplicate_email_policy,
+            mask: cfg.log_email_mask,
+            strip_plus_tags: cfg.strip_plus_tags,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn check(&mut self, user: &User) -> Result<(), PipelineError> {
+        if self.policy == DuplicateEmailPolicy::Allow {
+            return Ok(());
+        }
+
+        if self.seen.insert(dedup_key_with(user, self.strip_plus_tags)) {
+            return Ok(());
+        }
+
+        let email = mask_email_with(&user.email, self.mask);
+        match self.policy {
+            DuplicateEmailPolicy::Error => Err(PipelineError::DuplicateEmail { email }),
+            _ => {
+                warn!(email = %email, "duplicate email address");
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(Default)]
@@ -17,48 +201,391 @@ struct PipelineMetrics {
     lines_total: u64,
     lines_ok: u64,
     lines_err: u64,
+    failed_line_no: Option<usize>,
+    rejected_lines: Vec<usize>,
+    rejections: Vec<RejectedLine>,
 }
 
-/// Process multiple lines, short-circuiting on the first failure.
-#[instrument(name = "process_lines", level = "info", skip(lines, cfg))]
+/// A line skipped by a `continue_on_error` run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedLine {
+    /// 1-based line number in the input, counting blank and comment lines (the position
+    /// within the processed lines when the input does not number its lines).
+    pub line: usize,
+    pub error: PipelineError,
+    /// The line as read, with email addresses masked by `cfg.log_email_mask`; only kept when
+    /// `cfg.with_line_text` is set.
+    pub text: Option<String>,
+}
+
+/// How many lines a batch call accepted and rejected.
+///
+/// `failed` can only be non-zero when `cfg.continue_on_error` is set; otherwise the first
+/// failure is returned as an error instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessSummary {
+    pub processed: usize,
+    pub failed: usize,
+    /// 1-based positions (within the processed lines) of the lines counted in `failed`; empty
+    /// when `cfg.retain_rejections` is off.
+    pub rejected_lines: Vec<usize>,
+    /// Why each of `rejected_lines` was rejected, in the same order.
+    pub rejections: Vec<RejectedLine>,
+    /// Per-stage timings; `Some` only when `cfg.profile` is set.
+    pub timings: Option<StageTimings>,
+}
+
+impl ProcessSummary {
+    fn add_format_time(&mut self, spent: Duration) {
+        if let Some(timings) = &mut self.timings {
+            timings.format += spent;
+        }
+    }
+}
+
+/// Formatted output of [`process_lines_observed`] together with its summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessOutput {
+    pub lines: Vec<String>,
+    pub summary: ProcessSummary,
+}
+
+/// Process multiple lines, short-circuiting on the first failure unless
+/// `cfg.continue_on_error` is set, in which case failing lines are logged and skipped.
+///
+/// When `cfg.sort_by` is set, every record is buffered and sorted before formatting.
 pub fn process_lines<I>(lines: I, cfg: &ValidationConfig) -> Result<Vec<String>, PipelineError>
 where
-    I: IntoIterator<Item = String>,
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
 {
+    process_lines_observed(lines, cfg, |_| {}).map(|output| output.lines)
+}
+
+/// Like [`process_lines`], additionally passing each enriched record to `observe` in output
+/// order (for statistics such as `AgeHistogram`) and reporting a `ProcessSummary`.
+/// Nothing is observed if processing aborts.
+pub fn process_lines_observed<I, F>(
+    lines: I,
+    cfg: &ValidationConfig,
+    mut observe: F,
+) -> Result<ProcessOutput, PipelineError>
+where
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
+    F: FnMut(&EnrichedUser),
+{
+    let (records, mut summary) = collect_sorted(lines, cfg, &mut |_| {})?;
+    let mut spent = Duration::ZERO;
+    let lines = records
+        .iter()
+        .inspect(|enriched| observe(enriched))
+        .map(|enriched| format_record(enriched, cfg, &mut spent))
+        .collect();
+    summary.add_format_time(spent);
+    Ok(ProcessOutput { lines, summary })
+}
+
+/// Runs every stage except formatting, returning the structured records in output order.
+///
+/// ```
+/// use monadic_pipeline::{process_lines_enriched, ValidationConfig};
+///
+/// let lines = vec!["Alice,30,alice@example.com".to_string()];
+/// let records = process_lines_enriched(lines, &ValidationConfig::default()).unwrap();
+/// assert_eq!(records[0].username, "alice");
+/// ```
+#[instrument(name = "process_lines", level = "info", skip(lines, cfg))]
+pub fn process_lines_enriched<I>(
+    lines: I,
+    cfg: &ValidationConfig,
+) -> Result<Vec<EnrichedUser>, PipelineError>
+where
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
+{
+    collect_sorted(lines, cfg, &mut |_| {}).map(|(records, _)| records)
+}
+
+fn collect_sorted<I>(
+    lines: I,
+    cfg: &ValidationConfig,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(Vec<EnrichedUser>, ProcessSummary), PipelineError>
+where
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
+{
+    let (mut records, summary) = collect_enriched(lines, cfg, progress)?;
+    if let Some(key) = cfg.sort_by {
+        sort_enriched(&mut records, key, cfg.sort_desc);
+    }
+    Ok((records, summary))
+}
+
+/// Stable sort of enriched records by `key`; ties keep their input order in both directions.
+///
+/// `SortKey::AgeGroup` orders by group label.
+pub fn sort_enriched(records: &mut [EnrichedUser], key: SortKey, descending: bool) {
+    let compare = |a: &EnrichedUser, b: &EnrichedUser| match key {
+        SortKey::Name => a.user.name.cmp(&b.user.name),
+        SortKey::Age => a.user.age.cmp(&b.user.age),
+        SortKey::Username => a.username.cmp(&b.username),
+        SortKey::AgeGroup => a.age_group.label().cmp(b.age_group.label()),
+    };
+    if descending {
+        records.sort_by(|a, b| compare(b, a));
+    } else {
+        records.sort_by(compare);
+    }
+}
+
+/// Like [`process_lines`], but writes each formatted record to `writer` as soon as it is
+/// produced instead of buffering the whole output.
+///
+/// Processing stops at the first failing line (unless `cfg.continue_on_error` is set);
+/// records before it have already been written. When `cfg.sort_by` is set, records are still
+/// buffered so they can be sorted.
+///
+/// ```
+/// use monadic_pipeline::{process_lines_to_writer, OutputOptions, ValidationConfig};
+///
+/// let lines = vec!["Alice,30,alice@example.com".to_string()];
+/// let mut out = Vec::new();
+/// let summary =
+///     process_lines_to_writer(lines, &ValidationConfig::default(), &mut out, &OutputOptions::default())
+///         .unwrap();
+/// assert_eq!(summary.processed, 1);
+/// assert_eq!(out, b"Alice (30, 30s) -> username=alice\n");
+/// ```
+pub fn process_lines_to_writer<I, W>(
+    lines: I,
+    cfg: &ValidationConfig,
+    writer: W,
+    opts: &OutputOptions,
+) -> Result<ProcessSummary, PipelineError>
+where
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
+    W: Write,
+{
+    process_lines_with_progress(lines, cfg, writer, opts, |_| {})
+}
+
+/// Running totals passed to the `process_lines_with_progress` callback.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Lines read so far.
+    pub lines: usize,
+    pub processed: usize,
+    pub failed: usize,
+}
+
+/// Like [`process_lines_to_writer`], calling `progress` after every `cfg.report_every` lines
+/// and once more when the input ends (or processing aborts), unless that point was just
+/// reported.
+///
+/// ```
+/// use monadic_pipeline::{process_lines_with_progress, OutputOptions, ValidationConfig};
+///
+/// let lines = vec!["Alice,30,alice@example.com".to_string(); 5];
+/// let cfg = ValidationConfig::builder().report_every(2).build().unwrap();
+/// let mut seen = Vec::new();
+/// process_lines_with_progress(lines, &cfg, Vec::new(), &OutputOptions::default(), |p| {
+///     seen.push(p.lines)
+/// })
+/// .unwrap();
+/// assert_eq!(seen, [2, 4, 5]);
+/// ```
+#[instrument(name = "process_lines", level = "info", skip_all)]
+pub fn process_lines_with_progress<I, W, F>(
+    lines: I,
+    cfg: &ValidationConfig,
+    writer: W,
+    opts: &OutputOptions,
+    mut progress: F,
+) -> Result<ProcessSummary, PipelineError>
+where
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
+    W: Write,
+    F: FnMut(Progress),
+{
+    let mut out = RecordWriter::new(writer, *opts);
+    let mut spent = Duration::ZERO;
+    let mut write = |enriched: &EnrichedUser| {
+        out.write_record(&format_record(enriched, cfg, &mut spent))
+            .map_err(output_error)
+    };
+    let mut summary = match cfg.sort_by {
+        Some(_) => {
+            let (records, summary) = collect_sorted(lines, cfg, &mut progress)?;
+            records.iter().try_for_each(&mut write)?;
+            summary
+        }
+        None => for_each_enriched(lines, cfg, &mut progress, |enriched| write(&enriched))?,
+    };
+    out.finish().map_err(output_error)?;
+    summary.add_format_time(spent);
+    Ok(summary)
+}
+
+/// Fail once the share of rejected lines exceeds `cfg.max_error_rate` (see there).
+fn check_error_rate(
+    metrics: &PipelineMetrics,
+    cfg: &ValidationConfig,
+) -> Result<(), PipelineError> {
+    let Some(max_rate) = cfg.max_error_rate else {
+        return Ok(());
+    };
+    let total = metrics.lines_total as usize;
+    if total < cfg.error_rate_min_lines.max(1) {
+        return Ok(());
+    }
+    if metrics.lines_err as f64 / total as f64 > max_rate {
+        return Err(PipelineError::ErrorRateExceeded {
+            failed: metrics.lines_err as usize,
+            total,
+        });
+    }
+    Ok(())
+}
+
+fn output_error(err: io::Error) -> PipelineError {
+    PipelineError::Output {
+        reason: err.to_string(),
+    }
+}
+
+fn collect_enriched<I>(
+    lines: I,
+    cfg: &ValidationConfig,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(Vec<EnrichedUser>, ProcessSummary), PipelineError>
+where
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
+{
+    let mut records = Vec::new();
+    let summary = for_each_enriched(lines, cfg, progress, |enriched| {
+        records.push(enriched);
+        Ok(())
+    })?;
+    Ok((records, summary))
+}
+
+/// Drives every line through the stages, handing each record to `sink`, reporting `progress`
+/// every `cfg.report_every` lines and logging metrics.
+///
+/// Stage failures abort the run unless `cfg.continue_on_error` is set; errors returned by
+/// `sink` always abort.
+fn for_each_enriched<I, F>(
+    lines: I,
+    cfg: &ValidationConfig,
+    progress: &mut dyn FnMut(Progress),
+    mut sink: F,
+) -> Result<ProcessSummary, PipelineError>
+where
+    I: IntoIterator,
+    I::Item: Into<InputLine>,
+    F: FnMut(EnrichedUser) -> Result<(), PipelineError>,
+{
+    for key in cfg.unknown_label_overrides() {
+        warn!(key, "ignoring unknown age group label override");
+    }
+
     let mut metrics = PipelineMetrics::default();
+    let mut emails = EmailTracker::new(cfg);
+    let mut timings = cfg.profile.then(StageTimings::default);
+
+    let mut report = |metrics: &PipelineMetrics| {
+        progress(Progress {
+            lines: metrics.lines_total as usize,
+            processed: metrics.lines_ok as usize,
+            failed: metrics.lines_err as usize,
+        })
+    };
+    let mut reported = 0;
 
-    let result: Result<Vec<_>, _> = lines
-        .into_iter()
-        .map(|line| {
-            metrics.lines_total += 1;
-            match process_line(&line, cfg) {
-                Ok(formatted) => {
-                    metrics.lines_ok += 1;
-                    Ok(formatted)
+    let result = lines.into_iter().enumerate().try_for_each(|(idx, line)| {
+        let InputLine {
+            line_no: source_line,
+            text: line,
+        } = line.into();
+        metrics.lines_total += 1;
+        let line_no = idx + 1;
+        let source_line = source_line.unwrap_or(line_no);
+        let result = match process_numbered_line(
+            line_no,
+            source_line,
+            &line,
+            cfg,
+            &mut emails,
+            timings.as_mut(),
+        ) {
+            Ok(enriched) => {
+                metrics.lines_ok += 1;
+                if cfg.filter.matches(&enriched) {
+                    sink(enriched)
+                } else {
+                    debug!(line_no, "record dropped by filter");
+                    Ok(())
                 }
-                Err(err) => {
-                    metrics.lines_err += 1;
-                    Err(err)
+            }
+            Err(err) if cfg.continue_on_error => {
+                metrics.lines_err += 1;
+                let text = cfg
+                    .with_line_text
+                    .then(|| mask_emails_in(&line, cfg.log_email_mask));
+                warn!(line_no = source_line, error = %err, line_text = text.as_deref(), "skipping rejected line");
+                if cfg.retain_rejections {
+                    metrics.rejected_lines.push(line_no);
+                    metrics.rejections.push(RejectedLine {
+                        line: source_line,
+                        error: err,
+                        text,
+                    });
                 }
+                Ok(())
             }
-        })
-        .collect();
+            Err(err) => {
+                metrics.lines_err += 1;
+                metrics.failed_line_no = Some(source_line);
+                Err(err)
+            }
+        };
+        if cfg.report_every > 0 && line_no % cfg.report_every == 0 {
+            report(&metrics);
+            reported = line_no;
+        }
+        result.and_then(|()| check_error_rate(&metrics, cfg))
+    });
+    if reported != metrics.lines_total as usize {
+        report(&metrics);
+    }
 
     match result {
-        Ok(output) => {
+        Ok(()) => {
             info!(
                 lines_total = metrics.lines_total,
                 lines_ok = metrics.lines_ok,
                 lines_err = metrics.lines_err,
                 "successfully processed lines"
             );
-            Ok(output)
+            Ok(ProcessSummary {
+                processed: metrics.lines_ok as usize,
+                failed: metrics.lines_err as usize,
+                rejected_lines: metrics.rejected_lines,
+                rejections: metrics.rejections,
+                timings,
+            })
         }
         Err(err) => {
             error!(
                 lines_total = metrics.lines_total,
                 lines_ok = metrics.lines_ok,
                 lines_err = metrics.lines_err,
+                line_no = metrics.failed_line_no,
                 error = %err,
                 "pipeline aborted due to error"
             );
@@ -0,0 +1,122 @@
+use crate::domain::{EnrichedUser, PipelineError, User};
+use crate::validation::ValidationConfig;
+use crate::{enrich_user_with_config, format_with_config, parse_line_with, validate_user};
+use std::fmt;
+
+/// A typed chain of fallible steps from `I` to `O`, run against one input by [`execute`].
+///
+/// The stage constructors mirror `process_line` (`parse -> validate -> enrich -> format`)
+/// and custom steps can be interleaved with [`then`] and [`map`]; the first failing step
+/// short-circuits the rest.
+///
+/// ```
+/// use monadic_pipeline::pipeline::Pipeline;
+/// use monadic_pipeline::{PipelineError, ValidationConfig};
+///
+/// let cfg = ValidationConfig::default();
+/// let pipeline = Pipeline::parse(&cfg)
+///     .validate(&cfg)
+///     .then(|mut user| {
+///         if user.email.ends_with("@example.org") {
+///             return Err(PipelineError::InvalidConfig { reason: "no .org".into() });
+///         }
+///         user.name.make_ascii_uppercase();
+///         Ok(user)
+///     })
+///     .enrich(&cfg)
+///     .format(&cfg);
+///
+/// assert_eq!(
+///     pipeline.execute("Alice,30,alice@example.com").unwrap(),
+///     "ALICE (30, 30s) -> username=alice"
+/// );
+/// assert!(pipeline.execute("Bob,45,bob@example.org").is_err());
+/// ```
+///
+/// [`execute`]: Pipeline::execute
+/// [`then`]: Pipeline::then
+/// [`map`]: Pipeline::map
+pub struct Pipeline<'a, I, O> {
+    run: Box<dyn Fn(I) -> Result<O, PipelineError> + Send + Sync + 'a>,
+}
+
+impl<'a, I: 'a> Pipeline<'a, I, I> {
+    /// A pipeline that passes its input through unchanged.
+    pub fn new() -> Self {
+        Self { run: Box::new(Ok) }
+    }
+}
+
+impl<'a, I: 'a> Default for Pipeline<'a, I, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, I: AsRef<str> + 'a> Pipeline<'a, I, User> {
+    /// Start with `parse_line_with` under `cfg.parse`.
+    pub fn parse(cfg: &'a ValidationConfig) -> Self {
+        Self {
+            run: Box::new(move |line: I| parse_line_with(line.as_ref(), &cfg.parse)),
+        }
+    }
+
+    /// `parse`, `validate`, `enrich` and `format`: the same result as `process_line`.
+    pub fn standard(cfg: &'a ValidationConfig) -> Pipeline<'a, I, String> {
+        Self::parse(cfg).validate(cfg).enrich(cfg).format(cfg)
+    }
+}
+
+impl<'a, I: 'a, O: 'a> Pipeline<'a, I, O> {
+    /// Append a fallible step.
+    pub fn then<N, F>(self, step: F) -> Pipeline<'a, I, N>
+    where
+        F: Fn(O) -> Result<N, PipelineError> + Send + Sync + 'a,
+    {
+        let run = self.run;
+        Pipeline {
+            run: Box::new(move |input| run(input).and_then(&step)),
+        }
+    }
+
+    /// Append an infallible step.
+    pub fn map<N, F>(self, step: F) -> Pipeline<'a, I, N>
+    where
+        F: Fn(O) -> N + Send + Sync + 'a,
+    {
+        let run = self.run;
+        Pipeline {
+            run: Box::new(move |input| run(input).map(&step)),
+        }
+    }
+
+    /// Run every step against `input`.
+    pub fn execute(&self, input: I) -> Result<O, PipelineError> {
+        (self.run)(input)
+    }
+}
+
+impl<'a, I: 'a> Pipeline<'a, I, User> {
+    /// Append `validate_user` under `cfg`.
+    pub fn validate(self, cfg: &'a ValidationConfig) -> Self {
+        self.then(move |user| validate_user(user, cfg))
+    }
+
+    /// Append enrichment with `cfg.enrichers()`.
+    pub fn enrich(self, cfg: &'a ValidationConfig) -> Pipeline<'a, I, EnrichedUser> {
+        self.map(move |user| enrich_user_with_config(user, cfg))
+    }
+}
+
+impl<'a, I: 'a> Pipeline<'a, I, EnrichedUser> {
+    /// Append formatting as configured in `cfg` (template, CSV or `format_user`).
+    pub fn format(self, cfg: &'a ValidationConfig) -> Pipeline<'a, I, String> {
+        self.map(move |enriched| format_with_config(&enriched, cfg))
+    }
+}
+
+impl<I, O> fmt::Debug for Pipeline<'_, I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,109 @@
+use crate::domain::{PipelineError, User};
+use crate::mask_email_with;
+use crate::validation::{validate_email_detailed, ValidationConfig};
+use std::fmt;
+use std::sync::Arc;
+
+/// A single check applied to a parsed user by `validate_user`.
+///
+/// The built-in checks are implemented as rules too; custom rules registered on the config
+/// (see `ValidationConfigBuilder::rule`) run after them, in registration order.
+///
+/// ```
+/// use monadic_pipeline::{process_line, PipelineError, User, ValidationConfig, ValidationRule};
+///
+/// #[derive(Debug)]
+/// struct NoRobots;
+///
+/// impl ValidationRule for NoRobots {
+///     fn check(&self, user: &User) -> Result<(), PipelineError> {
+///         if user.name.eq_ignore_ascii_case("robot") {
+///             return Err(PipelineError::InvalidConfig { reason: "robots are not users".into() });
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let cfg = ValidationConfig::builder().rule(NoRobots).build().unwrap();
+/// assert!(process_line("Robot,30,robot@example.com", &cfg).is_err());
+/// assert!(process_line("Alice,30,alice@example.com", &cfg).is_ok());
+/// ```
+pub trait ValidationRule: fmt::Debug + Send + Sync {
+    fn check(&self, user: &User) -> Result<(), PipelineError>;
+
+    /// Name listed in provenance records (see `output::Provenance`); defaults to the type name
+    /// without its module path or generic parameters.
+    fn name(&self) -> &str {
+        let full = std::any::type_name::<Self>();
+        let base = full.split('<').next().unwrap_or(full);
+        base.rsplit("::").next().unwrap_or(base)
+    }
+}
+
+impl<R: ValidationRule + ?Sized> ValidationRule for Arc<R> {
+    fn check(&self, user: &User) -> Result<(), PipelineError> {
+        (**self).check(user)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+/// Rejects names that are empty or whitespace-only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NonEmptyName;
+
+impl ValidationRule for NonEmptyName {
+    fn check(&self, user: &User) -> Result<(), PipelineError> {
+        if user.name.trim().is_empty() {
+            return Err(PipelineError::EmptyName);
+        }
+        Ok(())
+    }
+}
+
+/// Enforces the configured age range, and whether an age must be present at all.
+#[derive(Debug, Clone, Copy)]
+pub struct AgeBounds {
+    pub min_age: u8,
+    pub max_age: u8,
+    pub require_age: bool,
+    /// Reject an age of 0 even when `min_age` allows it.
+    pub reject_zero_age: bool,
+}
+
+impl ValidationRule for AgeBounds {
+    fn check(&self, user: &User) -> Result<(), PipelineError> {
+        match user.age {
+            None if self.require_age => Err(PipelineError::MissingAge),
+            Some(age) if age < self.min_age => Err(PipelineError::InvalidAge {
+                age,
+                min_age: self.min_age,
+            }),
+            Some(0) if self.reject_zero_age => Err(PipelineError::InvalidAge {
+                age: 0,
+                min_age: self.min_age,
+            }),
+            Some(age) if age > self.max_age => Err(PipelineError::AgeOutOfRange { age }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Validates the email address with every email-related option of the config.
+#[derive(Debug, Clone, Copy)]
+pub struct EmailFormat<'a> {
+    pub cfg: &'a ValidationConfig,
+}
+
+impl ValidationRule for EmailFormat<'_> {
+    fn check(&self, user: &User) -> Result<(), PipelineError> {
+        validate_email_detailed(&user.email, self.cfg).map_err(|reason| {
+            PipelineError::InvalidEmail {
+                email: mask_email_with(&user.email, self.cfg.log_email_mask),
+                reason,
+            }
+        })
+    }
+}
@@ -0,0 +1,221 @@
+use crate::input::{InputLine, InputPart, InputSource};
+use crate::output::{write_lines, OutputOptions, OutputSink};
+use crate::parsing::ColumnOrder;
+use crate::pipeline::{process_lines_observed, process_lines_to_writer, ProcessSummary};
+use crate::stats::AgeHistogram;
+use crate::validation::ValidationConfig;
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Settings for [`run_pipeline`] that concern the run as a whole rather than single records.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub output: OutputOptions,
+    /// Process at most this many data lines.
+    pub limit: Option<usize>,
+    /// Write the number of accepted records instead of the records themselves, followed on a
+    /// second line by the number of rejected ones when `cfg.continue_on_error` is set.
+    pub count_only: bool,
+    /// Collect an `AgeHistogram` of the accepted records into `RunReport::histogram`.
+    pub histogram: bool,
+    /// Treat the first input line as a header row instead of data.
+    pub has_header: bool,
+    /// With `has_header`, take the column order from the header rather than
+    /// `cfg.parse.columns`.
+    pub infer_columns: bool,
+    /// Checked before each line; once set the run stops early, still flushing what was
+    /// written so far.
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+/// Outcome of [`run_pipeline`].
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    pub summary: ProcessSummary,
+    /// Present when `RunOptions::histogram` is set.
+    pub histogram: Option<AgeHistogram>,
+    /// Whether `RunOptions::stop` ended the run before the input was exhausted.
+    pub interrupted: bool,
+    /// Per-part breakdown of `summary` (see `InputSource::read_parts`); only filled in when
+    /// `cfg.continue_on_error` is set.
+    pub parts: Vec<PartSummary>,
+}
+
+/// How one input part (typically one file) fared in a `continue_on_error` run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartSummary {
+    pub name: String,
+    pub processed: usize,
+    pub failed: usize,
+    /// Why the part could not be read; none of its lines were processed.
+    pub error: Option<String>,
+}
+
+/// Read every line from `input`, run it through the pipeline and write the result to
+/// `output`: the whole flow the CLI performs, minus argument parsing and reporting.
+///
+/// Lines are processed as `InputSource::stream_numbered_lines` yields them, and records are
+/// streamed to `output` unless `count_only` or `histogram` require the full batch first; a
+/// read error part-way through fails the run after the records before it were written. With
+/// `cfg.continue_on_error`, the input is read part by part instead: unreadable parts are
+/// reported in `RunReport::parts` and skipped instead of failing the run.
+///
+/// ```
+/// use monadic_pipeline::{run_pipeline, MemorySink, MemorySource, RunOptions, ValidationConfig};
+///
+/// let input = MemorySource::new("Alice,30,alice@example.com\nBob,45,bob@example.com\n");
+/// let mut output = MemorySink::new();
+/// let report =
+///     run_pipeline(&input, &mut output, &ValidationConfig::default(), &RunOptions::default())
+///         .unwrap();
+/// assert_eq!(report.summary.processed, 2);
+/// assert!(output.contents().starts_with(b"Alice (30, 30s)"));
+/// ```
+pub fn run_pipeline<I>(
+    input: &I,
+    output: &mut dyn OutputSink,
+    cfg: &ValidationConfig,
+    opts: &RunOptions,
+) -> Result<RunReport>
+where
+    I: InputSource + ?Sized,
+{
+    let mut read_error = None;
+    let (mut lines, mut parts): (Box<dyn Iterator<Item = InputLine> + '_>, _) =
+        if cfg.continue_on_error {
+            let (lines, parts) = read_isolated(input, cfg);
+            info!(lines = lines.len(), "loaded input lines");
+            (Box::new(lines.into_iter()), parts)
+        } else {
+            let lines = input
+                .stream_numbered_lines(&cfg.parse)?
+                .map_while(|line| line.map_err(|err| read_error = Some(err)).ok());
+            (Box::new(lines), Vec::new())
+        };
+    let header_cfg;
+    let header = if opts.has_header { lines.next() } else { None };
+    let cfg = if let Some(InputLine { text: header, .. }) = header {
+        if let Some((_, len)) = parts.iter_mut().find(|(_, len)| *len > 0) {
+            *len -= 1;
+        }
+        if opts.infer_columns {
+            let mut inferred = cfg.clone();
+            inferred.parse.columns = ColumnOrder::from_header(&header, cfg.parse.delimiter)
+                .with_context(|| format!("failed to infer columns from header `{header}`"))?;
+            info!(columns = %inferred.parse.columns, "inferred columns from header");
+            header_cfg = inferred;
+            &header_cfg
+        } else {
+            cfg
+        }
+    } else {
+        cfg
+    };
+
+    let stopped = || {
+        opts.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    };
+    let lines = lines
+        .take(opts.limit.unwrap_or(usize::MAX))
+        .take_while(|_| !stopped());
+
+    let (summary, histogram) = if !opts.count_only && !opts.histogram {
+        let summary = process_lines_to_writer(lines, cfg, output.open()?, &opts.output)
+            .context("pipeline execution failed")?;
+        if let Some(err) = read_error {
+            return Err(err);
+        }
+        (summary, None)
+    } else {
+        let mut histogram = AgeHistogram::new();
+        let processed = process_lines_observed(lines, cfg, |enriched| {
+            if let Some(age) = enriched.user.age {
+                histogram.record(age);
+            }
+        })
+        .context("pipeline execution failed")?;
+        if let Some(err) = read_error {
+            return Err(err);
+        }
+
+        let records = if opts.count_only {
+            let mut counts = vec![processed.lines.len().to_string()];
+            if cfg.continue_on_error {
+                counts.push(processed.summary.failed.to_string());
+            }
+            counts
+        } else {
+            processed.lines
+        };
+        write_lines(output.open()?, &records, &opts.output).context("failed to write output")?;
+        (processed.summary, opts.histogram.then_some(histogram))
+    };
+
+    Ok(RunReport {
+        parts: attribute_to_parts(parts, &summary),
+        summary,
+        histogram,
+        interrupted: stopped(),
+    })
+}
+
+/// Read every part of `input`, logging and skipping the unreadable ones. Returns the
+/// concatenated lines and, per part, its summary so far and its number of lines.
+fn read_isolated<I>(
+    input: &I,
+    cfg: &ValidationConfig,
+) -> (Vec<InputLine>, Vec<(PartSummary, usize)>)
+where
+    I: InputSource + ?Sized,
+{
+    let mut lines = Vec::new();
+    let mut parts = Vec::new();
+    for InputPart { name, lines: read } in input.read_parts(&cfg.parse) {
+        let mut part = PartSummary {
+            name,
+            ..PartSummary::default()
+        };
+        let len = match read {
+            Ok(part_lines) => {
+                let len = part_lines.len();
+                lines.extend(part_lines);
+                len
+            }
+            Err(err) => {
+                warn!(part = %part.name, error = format!("{err:#}"), "skipping unreadable input");
+                part.error = Some(format!("{err:#}"));
+                0
+            }
+        };
+        parts.push((part, len));
+    }
+    (lines, parts)
+}
+
+/// Split `summary` across the parts its lines came from, in order. Lines after the last one
+/// the run consumed (because of `limit` or `stop`) count for nothing.
+fn attribute_to_parts(
+    parts: Vec<(PartSummary, usize)>,
+    summary: &ProcessSummary,
+) -> Vec<PartSummary> {
+    let consumed = summary.processed + summary.failed;
+    let mut start = 0;
+    parts
+        .into_iter()
+        .map(|(mut part, len)| {
+            let range = start + 1..=start + len;
+            part.failed = summary
+                .rejected_lines
+                .iter()
+                .filter(|line_no| range.contains(line_no))
+                .count();
+            part.processed = len.min(consumed.saturating_sub(start)) - part.failed;
+            start += len;
+            part
+        })
+        .collect()
+}
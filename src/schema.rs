@@ -0,0 +1,15 @@
+use crate::domain::{EnrichedUser, User};
+use serde_json::{json, Value};
+
+/// JSON Schemas for `User` and `EnrichedUser`, derived from their serde definitions.
+///
+/// ```
+/// let schemas = monadic_pipeline::schema::record_schemas();
+/// assert!(schemas["EnrichedUser"]["properties"]["username"].is_object());
+/// ```
+pub fn record_schemas() -> Value {
+    json!({
+        "User": schemars::schema_for!(User),
+        "EnrichedUser": schemars::schema_for!(EnrichedUser),
+    })
+}
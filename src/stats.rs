@@ -0,0 +1,171 @@
+use crate::domain::EnrichedUser;
+use std::time::Duration;
+
+/// Streaming tally of records per age group label.
+///
+/// Counts are accumulated one record at a time, so distribution stats can be gathered
+/// without buffering the records themselves.
+///
+/// ```
+/// use monadic_pipeline::{enrich_user, parse_line, AgeGroupCounter};
+///
+/// let mut counter = AgeGroupCounter::new();
+/// for line in ["Alice,30,alice@example.com", "Bob,34,bob@example.com"] {
+///     counter.record(&enrich_user(parse_line(line).unwrap()));
+/// }
+/// assert_eq!(counter.into_report(), vec![("30s".to_string(), 2)]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AgeGroupCounter {
+    counts: Vec<(String, u64)>,
+}
+
+impl AgeGroupCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `enriched` towards its age group.
+    pub fn record(&mut self, enriched: &EnrichedUser) {
+        let label = enriched.age_group.label();
+        match self.counts.iter_mut().find(|(seen, _)| seen == label) {
+            Some((_, count)) => *count += 1,
+            None => self.counts.push((label.to_owned(), 1)),
+        }
+    }
+
+    /// Total number of records seen so far.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Per-group counts, in the order each group was first seen.
+    pub fn into_report(self) -> Vec<(String, u64)> {
+        self.counts
+    }
+}
+
+/// Width of each `AgeHistogram` bin, in years.
+const HISTOGRAM_BIN_YEARS: u8 = 10;
+
+/// Age distribution in 10-year bins, renderable as an ASCII bar chart.
+///
+/// ```
+/// use monadic_pipeline::AgeHistogram;
+///
+/// let mut histogram = AgeHistogram::new();
+/// for age in [31, 35, 47] {
+///     histogram.record(age);
+/// }
+/// assert_eq!(histogram.render(20), "30-39 | ########## 2\n40-49 | ##### 1\n");
+/// assert_eq!(AgeHistogram::new().render(80), "no data\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AgeHistogram {
+    bins: Vec<u64>,
+}
+
+impl AgeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, age: u8) {
+        let bin = usize::from(age / HISTOGRAM_BIN_YEARS);
+        if self.bins.len() <= bin {
+            self.bins.resize(bin + 1, 0);
+        }
+        self.bins[bin] += 1;
+    }
+
+    /// Render one bar per bin from the youngest to the oldest populated bin, scaling the
+    /// longest bar so each line fits in `width` columns.
+    pub fn render(&self, width: usize) -> String {
+        let Some(first) = self.bins.iter().position(|&count| count > 0) else {
+            return "no data\n".to_string();
+        };
+        let years = usize::from(HISTOGRAM_BIN_YEARS);
+        let rows: Vec<(String, u64)> = self.bins[first..]
+            .iter()
+            .enumerate()
+            .map(|(offset, &count)| {
+                let start = (first + offset) * years;
+                (format!("{}-{}", start, start + years - 1), count)
+            })
+            .collect();
+        let max_count = rows.iter().map(|(_, count)| *count).max().unwrap_or(1);
+        let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let count_width = max_count.to_string().len();
+        // Each row is "<label> | <bar> <count>".
+        let bar_width = width.saturating_sub(label_width + count_width + 4).max(1) as u64;
+
+        let mut out = String::new();
+        for (label, count) in rows {
+            let mut bar = count * bar_width / max_count;
+            if count > 0 {
+                bar = bar.max(1);
+            }
+            out.push_str(&format!(
+                "{label:<label_width$} | {} {count}\n",
+                "#".repeat(bar as usize)
+            ));
+        }
+        out
+    }
+}
+
+/// Wall-clock time spent in each pipeline stage, accumulated over a run when
+/// `ValidationConfig::profile` is set.
+///
+/// ```
+/// use monadic_pipeline::StageTimings;
+/// use std::time::Duration;
+///
+/// let timings = StageTimings {
+///     parse: Duration::from_millis(3),
+///     validate: Duration::from_millis(1),
+///     ..StageTimings::default()
+/// };
+/// assert_eq!(timings.total(), Duration::from_millis(4));
+/// assert!(timings.render().starts_with("stage    "));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageTimings {
+    pub parse: Duration,
+    pub validate: Duration,
+    pub enrich: Duration,
+    pub format: Duration,
+}
+
+impl StageTimings {
+    pub fn total(&self) -> Duration {
+        self.parse + self.validate + self.enrich + self.format
+    }
+
+    /// Render a table with one row per stage (milliseconds and share of the total) plus a
+    /// total row.
+    pub fn render(&self) -> String {
+        let total = self.total();
+        let rows = [
+            ("parse", self.parse),
+            ("validate", self.validate),
+            ("enrich", self.enrich),
+            ("format", self.format),
+            ("total", total),
+        ];
+
+        let mut out = format!("{:<8} {:>12} {:>7}\n", "stage", "time (ms)", "share");
+        for (stage, elapsed) in rows {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                elapsed.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            out.push_str(&format!(
+                "{stage:<8} {:>12.3} {share:>6.1}%\n",
+                elapsed.as_secs_f64() * 1000.0
+            ));
+        }
+        out
+    }
+}
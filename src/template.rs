@@ -0,0 +1,176 @@
+use crate::domain::{EmailMaskPolicy, EnrichedUser, PipelineError};
+use crate::mask_email_with;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Field that can be referenced from an output template.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Placeholder {
+    Name,
+    Age,
+    AgeGroup,
+    Username,
+    Email,
+    RecordHash,
+}
+
+impl Placeholder {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(Self::Name),
+            "age" => Some(Self::Age),
+            "age_group" => Some(Self::AgeGroup),
+            "username" => Some(Self::Username),
+            "email" => Some(Self::Email),
+            "record_hash" => Some(Self::RecordHash),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Placeholder),
+}
+
+/// Output template such as `"{username}:{age}"`, parsed once and rendered per record.
+///
+/// Supported placeholders are `{name}`, `{age}`, `{age_group}`, `{username}`, `{email}` and
+/// `{record_hash}`; `{{` and `}}` emit literal braces. `{age}` and `{record_hash}` render as an
+/// empty string when absent.
+///
+/// ```
+/// use monadic_pipeline::{enrich_user, parse_line, OutputTemplate};
+///
+/// let template: OutputTemplate = "{username}:{age}".parse().unwrap();
+/// let enriched = enrich_user(parse_line("Alice,30,alice@example.com").unwrap());
+/// assert_eq!(template.render(&enriched), "alice:30");
+///
+/// assert!("{nickname}".parse::<OutputTemplate>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OutputTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl OutputTemplate {
+    pub fn parse(source: &str) -> Result<Self, PipelineError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(invalid(format!("unclosed placeholder `{{{name}`")))
+                            }
+                        }
+                    }
+                    let placeholder = Placeholder::from_name(&name).ok_or_else(|| {
+                        invalid(format!("unknown template placeholder `{{{name}}}`"))
+                    })?;
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(placeholder));
+                }
+                '}' => return Err(invalid("unmatched `}` in template (use `}}`)".into())),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            segments,
+        })
+    }
+
+    /// Render a single record according to the template.
+    pub fn render(&self, enriched: &EnrichedUser) -> String {
+        self.render_masked(enriched, EmailMaskPolicy::None)
+    }
+
+    /// Like [`render`](Self::render), masking `{email}` according to `email_mask`.
+    pub fn render_masked(&self, enriched: &EnrichedUser, email_mask: EmailMaskPolicy) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Field(Placeholder::Name) => out.push_str(&enriched.user.name),
+                Segment::Field(Placeholder::Age) => {
+                    if let Some(age) = enriched.user.age {
+                        out.push_str(&age.to_string());
+                    }
+                }
+                Segment::Field(Placeholder::AgeGroup) => out.push_str(enriched.age_group.label()),
+                Segment::Field(Placeholder::Username) => out.push_str(&enriched.username),
+                Segment::Field(Placeholder::Email) => {
+                    out.push_str(&mask_email_with(&enriched.user.email, email_mask))
+                }
+                Segment::Field(Placeholder::RecordHash) => {
+                    out.push_str(enriched.record_hash.as_deref().unwrap_or_default())
+                }
+            }
+        }
+        out
+    }
+
+    /// The template string as originally supplied.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+fn invalid(reason: String) -> PipelineError {
+    PipelineError::InvalidConfig { reason }
+}
+
+impl FromStr for OutputTemplate {
+    type Err = PipelineError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Self::parse(source)
+    }
+}
+
+impl TryFrom<String> for OutputTemplate {
+    type Error = PipelineError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
+
+impl From<OutputTemplate> for String {
+    fn from(template: OutputTemplate) -> Self {
+        template.source
+    }
+}
+
+impl fmt::Display for OutputTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
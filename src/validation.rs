@@ -1,57 +1,867 @@
-use crate::domain::AgeGroupingMode;
+use crate::domain::{
+    AgeBucket, AgeGroupLabels, AgeGroupingMode, AgeOverRangePolicy, DuplicateEmailPolicy,
+    EmailError, EmailMaskPolicy, PipelineError, RecordFilter, SortKey, UsernameScheme,
+};
+use crate::enrichers::{AgeGroupEnricher, Enricher, UsernameEnricher};
+use crate::output::{OutputFormat, Provenance};
+use crate::parsing::ParseOptions;
+use crate::rules::{AgeBounds, EmailFormat, NonEmptyName, ValidationRule};
+use crate::template::OutputTemplate;
+use crate::{DEFAULT_AGE_GROUP_LABELS, MAX_SUPPORTED_AGE};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Version of the serialised `ValidationConfig` format written by this crate.
+///
+/// Version 1 is the original, unversioned format, in which `min_age`, `strict_email` and
+/// `age_grouping` were required. Version 2 adds `config_version` and makes every field optional.
+pub const CONFIG_VERSION: u32 = 2;
 
 /// Configuration toggles for the validation step.
+///
+/// Load config files through [`ValidationConfig::from_json`], which migrates older versions.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationConfig {
+    /// Format version (see `CONFIG_VERSION`); files without it are version 1.
+    #[serde(default = "legacy_config_version")]
+    pub config_version: u32,
+    #[serde(default)]
     pub min_age: u8,
+    #[serde(default)]
     pub strict_email: bool,
+    #[serde(default)]
     pub age_grouping: AgeGroupingMode,
+    /// Bucket width in years for `AgeGroupingMode::FineGrained`, from 1 to `MAX_SUPPORTED_AGE`.
+    #[serde(default = "default_fine_grained_width")]
+    pub fine_grained_width: u8,
+    /// Non-overlapping age ranges for `AgeGroupingMode::Custom`; ages outside all of them are
+    /// grouped as `UNSPECIFIED_AGE_GROUP`.
+    #[serde(default)]
+    pub age_buckets: Vec<AgeBucket>,
+    /// Attach an email provider classification during enrichment.
+    #[serde(default)]
+    pub classify_provider: bool,
+    /// Overrides `MAX_SUPPORTED_AGE` as the upper age bound when set.
+    #[serde(default)]
+    pub supported_age_ceiling: Option<u8>,
+    /// What to do with ages above `age_ceiling`.
+    #[serde(default)]
+    pub age_over_range_policy: AgeOverRangePolicy,
+    /// Options applied when parsing raw lines.
+    #[serde(default)]
+    pub parse: ParseOptions,
+    /// Reaction to repeated email addresses across lines in `process_lines`.
+    #[serde(default)]
+    pub duplicate_email_policy: DuplicateEmailPolicy,
+    /// Ignore a `+tag` in the email's local part (`alice+news@example.com`) when comparing
+    /// emails for `duplicate_email_policy` and when a username falls back to the local part.
+    /// The stored email is left unchanged.
+    #[serde(default)]
+    pub strip_plus_tags: bool,
+    /// Custom output template; `format_user` is used when unset.
+    #[serde(default)]
+    pub template: Option<OutputTemplate>,
+    /// Record format for batch output; `Csv` cannot be combined with `template`.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Accept Unicode domain labels (e.g. `münchen.de`) in strict email mode.
+    /// Lax mode never restricts the domain character set.
+    #[serde(default)]
+    pub allow_idn: bool,
+    /// Fewest non-empty dot-separated domain labels a lax-mode email may have, so the default
+    /// of 2 rejects `alice@.com`. Strict mode enforces its own domain shape instead.
+    #[serde(default = "default_min_domain_labels")]
+    pub min_domain_labels: u8,
+    /// Fewest characters the last domain label of a lax-mode email may have, so the default
+    /// of 2 rejects `a@b.c`.
+    #[serde(default = "default_min_tld_len")]
+    pub min_tld_len: u8,
+    /// Drop enriched records that do not match, after validation, in batch processing.
+    /// Dropped records still count as processed, not failed.
+    #[serde(default)]
+    pub filter: RecordFilter,
+    /// Sort output records by this field; requires buffering the whole input.
+    #[serde(default)]
+    pub sort_by: Option<SortKey>,
+    /// Reverse the `sort_by` order.
+    #[serde(default)]
+    pub sort_desc: bool,
+    /// Display labels for the age group buckets (e.g. a translation), English by default.
+    #[serde(default)]
+    pub labels: AgeGroupLabels,
+    /// Replacement labels for `AgeGroupingMode::Default` buckets, keyed by the built-in label
+    /// (see `DEFAULT_AGE_GROUP_LABELS`). Unknown keys are ignored with a warning.
+    #[serde(default)]
+    pub label_overrides: HashMap<String, String>,
+    /// Masking applied to email addresses carried by errors and log events.
+    #[serde(default)]
+    pub log_email_mask: EmailMaskPolicy,
+    /// Masking applied to `{email}` in output templates.
+    #[serde(default = "default_output_email_mask")]
+    pub output_email_mask: EmailMaskPolicy,
+    /// When set, email domains must end in one of these lowercase TLDs (without the dot).
+    #[serde(default)]
+    pub valid_tlds: Option<HashSet<String>>,
+    /// Log and skip failing lines in batch processing instead of aborting on the first one.
+    #[serde(default)]
+    pub continue_on_error: bool,
+    /// With `continue_on_error`, abort with `PipelineError::ErrorRateExceeded` once more than
+    /// this fraction (0.0 to 1.0) of the lines read so far were rejected, after at least
+    /// `error_rate_min_lines` lines.
+    #[serde(default)]
+    pub max_error_rate: Option<f64>,
+    /// Lines to read before `max_error_rate` is checked, so a few early failures do not abort.
+    #[serde(default = "default_error_rate_min_lines")]
+    pub error_rate_min_lines: usize,
+    /// Keep the (email-masked) text of lines skipped by `continue_on_error`, for the rejection
+    /// log event and `pipeline::RejectedLine::text`.
+    #[serde(default)]
+    pub with_line_text: bool,
+    /// Keep every line skipped by `continue_on_error` in `ProcessSummary::rejected_lines` and
+    /// `rejections`. Turn off for unbounded input, where rejections are only logged; the
+    /// per-part failure counts of `run_pipeline` and the CLI's error records need them kept.
+    /// Not serialised.
+    #[serde(skip, default = "default_retain_rejections")]
+    pub retain_rejections: bool,
+    /// Reject users whose age field is empty; when false they skip the age checks and land
+    /// in the `unknown` age group.
+    #[serde(default = "default_require_age")]
+    pub require_age: bool,
+    /// Reject an age of 0 as `PipelineError::InvalidAge` even when `min_age` is 0, since it is
+    /// usually a placeholder rather than a real age.
+    #[serde(default)]
+    pub reject_zero_age: bool,
+    /// How usernames are derived during enrichment.
+    #[serde(default)]
+    pub username_scheme: UsernameScheme,
+    /// Lines between calls of the `pipeline::process_lines_with_progress` callback, which is
+    /// also called once at the end; 0 reports only at the end.
+    #[serde(default = "default_report_every")]
+    pub report_every: usize,
+    /// Attach `EnrichedUser::record_hash` during enrichment and include it in the output.
+    #[serde(default)]
+    pub record_hash: bool,
+    /// Measure time spent per stage in batch processing (see `ProcessSummary::timings`).
+    #[serde(default)]
+    pub profile: bool,
+    /// Highlight the age group and username of the default text output with ANSI colors
+    /// (see `output::format_user_colored`). Templates and CSV output are never colored.
+    #[serde(default)]
+    pub color: bool,
+    /// Collapse runs of whitespace inside names to single spaces during validation, so the
+    /// output, username and initials see the normalised name.
+    #[serde(default)]
+    pub collapse_name_whitespace: bool,
+    /// Stop after validation: records are written as `name,age,email` CSV rows (see
+    /// `output::user_row`) and the derived fields of `EnrichedUser` are left empty.
+    #[serde(default)]
+    pub skip_enrichment: bool,
+    /// When set, each record is written as JSON wrapped with this metadata (see
+    /// `output::provenance_record`) instead of in `output_format`. Not serialised.
+    #[serde(skip)]
+    pub provenance: Option<Provenance>,
+    /// Extra rules run by `validate_user` after the built-in ones, in order. Not serialised.
+    #[serde(skip)]
+    pub custom_rules: Vec<Arc<dyn ValidationRule>>,
+    /// Extra enrichers run after the built-in ones, in order. Not serialised.
+    #[serde(skip)]
+    pub custom_enrichers: Vec<Arc<dyn Enricher>>,
+}
+
+fn legacy_config_version() -> u32 {
+    1
+}
+
+fn default_retain_rejections() -> bool {
+    true
+}
+
+fn default_require_age() -> bool {
+    true
+}
+
+pub(crate) fn default_fine_grained_width() -> u8 {
+    5
+}
+
+fn default_error_rate_min_lines() -> usize {
+    20
+}
+
+fn default_report_every() -> usize {
+    1000
+}
+
+fn default_min_domain_labels() -> u8 {
+    2
+}
+
+fn default_min_tld_len() -> u8 {
+    2
+}
+
+fn default_output_email_mask() -> EmailMaskPolicy {
+    EmailMaskPolicy::None
 }
 
 impl ValidationConfig {
+    /// Starts a `ValidationConfigBuilder` seeded with the default configuration.
+    pub fn builder() -> ValidationConfigBuilder {
+        ValidationConfigBuilder::default()
+    }
+
     pub fn new(min_age: u8, strict_email: bool, age_grouping: AgeGroupingMode) -> Self {
         Self {
             min_age,
             strict_email,
             age_grouping,
+            ..Self::default()
         }
     }
+
+    /// Parse a JSON config file, upgrading older format versions to `CONFIG_VERSION`.
+    ///
+    /// Unknown keys are ignored with a warning rather than rejected, so files written by newer
+    /// minor releases still load; a newer `config_version` is an error.
+    ///
+    /// ```
+    /// use monadic_pipeline::{ValidationConfig, CONFIG_VERSION};
+    ///
+    /// // A version 1 file: no `config_version`, only the original fields.
+    /// let cfg = ValidationConfig::from_json(
+    ///     r#"{"min_age": 18, "strict_email": true, "age_grouping": "default"}"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(cfg.config_version, CONFIG_VERSION);
+    /// assert!(cfg.require_age);
+    ///
+    /// assert!(ValidationConfig::from_json(r#"{"config_version": 99}"#).is_err());
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self, PipelineError> {
+        let invalid = |reason: String| PipelineError::InvalidConfig { reason };
+        let mut value: Value = serde_json::from_str(json)
+            .map_err(|err| invalid(format!("malformed config: {err}")))?;
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| invalid("config must be a JSON object".into()))?;
+
+        let version = match object.get("config_version") {
+            None => legacy_config_version(),
+            Some(raw) => raw
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| invalid(format!("invalid config_version `{raw}`")))?,
+        };
+        if version > CONFIG_VERSION {
+            return Err(invalid(format!(
+                "config_version {version} is newer than the supported version {CONFIG_VERSION}"
+            )));
+        }
+        migrate_config(object, version);
+
+        let known = serde_json::to_value(Self::default()).expect("default config serialises");
+        for key in unknown_keys(object, &known, "") {
+            warn!(key, "ignoring unknown config key");
+        }
+
+        serde_json::from_value(value).map_err(|err| invalid(format!("invalid config: {err}")))
+    }
+
+    /// This config as JSON with `valid_tlds` sorted, so equal configs serialise identically.
+    pub fn canonical_json(&self) -> Value {
+        let mut config = serde_json::to_value(self).expect("config serialises");
+        // `valid_tlds` is a set; sort it so the output does not depend on hash order.
+        if let Some(tlds) = config
+            .get_mut("valid_tlds")
+            .and_then(|tlds| tlds.as_array_mut())
+        {
+            tlds.sort_by_key(|tld| tld.to_string());
+        }
+        config
+    }
+
+    /// Upper age bound enforced by `validate_user`.
+    pub fn age_ceiling(&self) -> u8 {
+        self.supported_age_ceiling.unwrap_or(MAX_SUPPORTED_AGE)
+    }
+
+    /// The ordered rules `validate_user` applies: name, age and email checks built from this
+    /// config, followed by `custom_rules`.
+    pub fn rules(&self) -> Vec<Box<dyn ValidationRule + '_>> {
+        let mut rules: Vec<Box<dyn ValidationRule + '_>> = vec![
+            Box::new(NonEmptyName),
+            Box::new(AgeBounds {
+                min_age: self.min_age,
+                max_age: self.age_ceiling(),
+                require_age: self.require_age,
+                reject_zero_age: self.reject_zero_age,
+            }),
+            Box::new(EmailFormat { cfg: self }),
+        ];
+        rules.extend(
+            self.custom_rules
+                .iter()
+                .map(|rule| Box::new(Arc::clone(rule)) as Box<dyn ValidationRule>),
+        );
+        rules
+    }
+
+    /// The ordered enrichers the pipeline runs on each validated user: age group and username
+    /// built from this config, followed by `custom_enrichers`.
+    pub fn enrichers(&self) -> Vec<Box<dyn Enricher + '_>> {
+        let mut enrichers: Vec<Box<dyn Enricher + '_>> = vec![
+            Box::new(AgeGroupEnricher {
+                mode: self.age_grouping,
+                fine_grained_width: self.fine_grained_width,
+                buckets: &self.age_buckets,
+                labels: &self.labels,
+                overrides: &self.label_overrides,
+            }),
+            Box::new(UsernameEnricher {
+                scheme: self.username_scheme,
+                strip_plus_tags: self.strip_plus_tags,
+            }),
+        ];
+        enrichers.extend(
+            self.custom_enrichers
+                .iter()
+                .map(|enricher| Box::new(Arc::clone(enricher)) as Box<dyn Enricher>),
+        );
+        enrichers
+    }
+
+    /// Keys in `label_overrides` that do not name a default age bucket, sorted.
+    pub fn unknown_label_overrides(&self) -> Vec<&str> {
+        let mut unknown: Vec<&str> = self
+            .label_overrides
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !DEFAULT_AGE_GROUP_LABELS.contains(key))
+            .collect();
+        unknown.sort_unstable();
+        unknown
+    }
+
+    /// Checks that the configured options are mutually consistent.
+    pub fn validate(&self) -> Result<(), PipelineError> {
+        if self.age_ceiling() < self.min_age {
+            return Err(PipelineError::InvalidConfig {
+                reason: format!(
+                    "supported age ceiling {} is below min_age {}",
+                    self.age_ceiling(),
+                    self.min_age
+                ),
+            });
+        }
+        if !(1..=MAX_SUPPORTED_AGE).contains(&self.fine_grained_width) {
+            return Err(PipelineError::InvalidConfig {
+                reason: format!(
+                    "fine_grained_width {} is outside 1..={}",
+                    self.fine_grained_width, MAX_SUPPORTED_AGE
+                ),
+            });
+        }
+        self.validate_age_buckets()?;
+        if let Some(rate) = self.max_error_rate {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(PipelineError::InvalidConfig {
+                    reason: format!("max_error_rate {rate} is outside 0.0..=1.0"),
+                });
+            }
+        }
+        if matches!(self.parse.delimiter, '"' | '\n' | '\r') {
+            return Err(PipelineError::InvalidConfig {
+                reason: format!(
+                    "{:?} cannot be used as the field delimiter",
+                    self.parse.delimiter
+                ),
+            });
+        }
+        if let Some(widths) = &self.parse.fixed_widths {
+            if widths.len() != self.parse.columns.columns().len() || widths.contains(&0) {
+                return Err(PipelineError::InvalidConfig {
+                    reason: format!(
+                        "fixed_widths needs one non-zero width per column, got {widths:?}"
+                    ),
+                });
+            }
+        }
+        if self.output_format != OutputFormat::Text && self.template.is_some() {
+            return Err(PipelineError::InvalidConfig {
+                reason: "an output template cannot be combined with CSV or JSON output".into(),
+            });
+        }
+        if self.skip_enrichment && self.output_format == OutputFormat::Json {
+            return Err(PipelineError::InvalidConfig {
+                reason: "skip_enrichment writes CSV rows and cannot be combined with JSON output"
+                    .into(),
+            });
+        }
+        if self.skip_enrichment && self.template.is_some() {
+            return Err(PipelineError::InvalidConfig {
+                reason: "an output template cannot be combined with skip_enrichment".into(),
+            });
+        }
+        if self.provenance.is_some()
+            && (self.skip_enrichment
+                || self.template.is_some()
+                || self.output_format != OutputFormat::Text)
+        {
+            return Err(PipelineError::InvalidConfig {
+                reason: "provenance records cannot be combined with a template, CSV or JSON \
+                         output or skip_enrichment"
+                    .into(),
+            });
+        }
+        Ok(())
+    }
+
+    fn validate_age_buckets(&self) -> Result<(), PipelineError> {
+        let invalid = |reason: String| Err(PipelineError::InvalidConfig { reason });
+        if self.age_grouping == AgeGroupingMode::Custom && self.age_buckets.is_empty() {
+            return invalid("custom age grouping needs at least one age bucket".into());
+        }
+        if let Some(bucket) = self.age_buckets.iter().find(|b| b.min > b.max) {
+            return invalid(format!(
+                "age bucket `{}` has min {} above max {}",
+                bucket.label, bucket.min, bucket.max
+            ));
+        }
+        let mut sorted: Vec<&AgeBucket> = self.age_buckets.iter().collect();
+        sorted.sort_by_key(|bucket| bucket.min);
+        if let Some(pair) = sorted.windows(2).find(|pair| pair[1].min <= pair[0].max) {
+            return invalid(format!(
+                "age buckets `{}` ({}-{}) and `{}` ({}-{}) overlap",
+                pair[0].label, pair[0].min, pair[0].max, pair[1].label, pair[1].min, pair[1].max
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Default for ValidationConfig {
     fn default() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             min_age: 0,
             strict_email: false,
             age_grouping: AgeGroupingMode::Default,
+            fine_grained_width: default_fine_grained_width(),
+            age_buckets: Vec::new(),
+            classify_provider: false,
+            supported_age_ceiling: None,
+            age_over_range_policy: AgeOverRangePolicy::default(),
+            parse: ParseOptions::default(),
+            duplicate_email_policy: DuplicateEmailPolicy::Allow,
+            strip_plus_tags: false,
+            template: None,
+            output_format: OutputFormat::default(),
+            allow_idn: false,
+            min_domain_labels: default_min_domain_labels(),
+            min_tld_len: default_min_tld_len(),
+            filter: RecordFilter::default(),
+            sort_by: None,
+            sort_desc: false,
+            labels: AgeGroupLabels::default(),
+            label_overrides: HashMap::new(),
+            log_email_mask: EmailMaskPolicy::default(),
+            output_email_mask: default_output_email_mask(),
+            valid_tlds: None,
+            continue_on_error: false,
+            max_error_rate: None,
+            error_rate_min_lines: default_error_rate_min_lines(),
+            with_line_text: false,
+            require_age: default_require_age(),
+            retain_rejections: default_retain_rejections(),
+            reject_zero_age: false,
+            username_scheme: UsernameScheme::default(),
+            report_every: default_report_every(),
+            record_hash: false,
+            profile: false,
+            color: false,
+            collapse_name_whitespace: false,
+            skip_enrichment: false,
+            provenance: None,
+            custom_rules: Vec::new(),
+            custom_enrichers: Vec::new(),
         }
     }
 }
 
+/// Chainable builder for `ValidationConfig` that checks invariants on `build()`.
+///
+/// ```
+/// use monadic_pipeline::{AgeGroupingMode, ValidationConfig};
+///
+/// let cfg = ValidationConfig::builder()
+///     .min_age(18)
+///     .max_age(99)
+///     .strict_email(true)
+///     .age_grouping(AgeGroupingMode::Wide)
+///     .build()
+///     .expect("consistent config");
+/// assert_eq!(cfg.age_ceiling(), 99);
+///
+/// assert!(ValidationConfig::builder().min_age(50).max_age(40).build().is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ValidationConfigBuilder {
+    config: ValidationConfig,
+}
+
+impl ValidationConfigBuilder {
+    pub fn min_age(mut self, min_age: u8) -> Self {
+        self.config.min_age = min_age;
+        self
+    }
+
+    /// Sets the upper age bound (`supported_age_ceiling`).
+    pub fn max_age(mut self, max_age: u8) -> Self {
+        self.config.supported_age_ceiling = Some(max_age);
+        self
+    }
+
+    pub fn age_over_range_policy(mut self, policy: AgeOverRangePolicy) -> Self {
+        self.config.age_over_range_policy = policy;
+        self
+    }
+
+    pub fn labels(mut self, labels: AgeGroupLabels) -> Self {
+        self.config.labels = labels;
+        self
+    }
+
+    pub fn strict_email(mut self, strict_email: bool) -> Self {
+        self.config.strict_email = strict_email;
+        self
+    }
+
+    pub fn age_grouping(mut self, age_grouping: AgeGroupingMode) -> Self {
+        self.config.age_grouping = age_grouping;
+        self
+    }
+
+    pub fn fine_grained_width(mut self, width: u8) -> Self {
+        self.config.fine_grained_width = width;
+        self
+    }
+
+    /// Group ages by `buckets`, switching to `AgeGroupingMode::Custom`.
+    pub fn age_buckets(mut self, buckets: Vec<AgeBucket>) -> Self {
+        self.config.age_grouping = AgeGroupingMode::Custom;
+        self.config.age_buckets = buckets;
+        self
+    }
+
+    pub fn allow_idn(mut self, allow_idn: bool) -> Self {
+        self.config.allow_idn = allow_idn;
+        self
+    }
+
+    pub fn min_domain_labels(mut self, min_domain_labels: u8) -> Self {
+        self.config.min_domain_labels = min_domain_labels;
+        self
+    }
+
+    pub fn min_tld_len(mut self, min_tld_len: u8) -> Self {
+        self.config.min_tld_len = min_tld_len;
+        self
+    }
+
+    pub fn classify_provider(mut self, classify_provider: bool) -> Self {
+        self.config.classify_provider = classify_provider;
+        self
+    }
+
+    pub fn duplicate_email_policy(mut self, policy: DuplicateEmailPolicy) -> Self {
+        self.config.duplicate_email_policy = policy;
+        self
+    }
+
+    pub fn strip_plus_tags(mut self, enabled: bool) -> Self {
+        self.config.strip_plus_tags = enabled;
+        self
+    }
+
+    pub fn filter(mut self, filter: RecordFilter) -> Self {
+        self.config.filter = filter;
+        self
+    }
+
+    pub fn sort_by(mut self, key: SortKey, descending: bool) -> Self {
+        self.config.sort_by = Some(key);
+        self.config.sort_desc = descending;
+        self
+    }
+
+    pub fn label_override(mut self, bucket: impl Into<String>, label: impl Into<String>) -> Self {
+        self.config
+            .label_overrides
+            .insert(bucket.into(), label.into());
+        self
+    }
+
+    pub fn email_masks(mut self, log: EmailMaskPolicy, output: EmailMaskPolicy) -> Self {
+        self.config.log_email_mask = log;
+        self.config.output_email_mask = output;
+        self
+    }
+
+    pub fn valid_tlds<I, S>(mut self, tlds: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.config.valid_tlds = Some(
+            tlds.into_iter()
+                .map(|tld| tld.as_ref().trim_start_matches('.').to_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    pub fn continue_on_error(mut self, enabled: bool) -> Self {
+        self.config.continue_on_error = enabled;
+        self
+    }
+
+    pub fn max_error_rate(mut self, rate: f64, min_lines: usize) -> Self {
+        self.config.max_error_rate = Some(rate);
+        self.config.error_rate_min_lines = min_lines;
+        self
+    }
+
+    pub fn with_line_text(mut self, enabled: bool) -> Self {
+        self.config.with_line_text = enabled;
+        self
+    }
+
+    pub fn retain_rejections(mut self, retain: bool) -> Self {
+        self.config.retain_rejections = retain;
+        self
+    }
+
+    pub fn require_age(mut self, required: bool) -> Self {
+        self.config.require_age = required;
+        self
+    }
+
+    pub fn reject_zero_age(mut self, enabled: bool) -> Self {
+        self.config.reject_zero_age = enabled;
+        self
+    }
+
+    pub fn username_scheme(mut self, scheme: UsernameScheme) -> Self {
+        self.config.username_scheme = scheme;
+        self
+    }
+
+    pub fn report_every(mut self, lines: usize) -> Self {
+        self.config.report_every = lines;
+        self
+    }
+
+    pub fn record_hash(mut self, enabled: bool) -> Self {
+        self.config.record_hash = enabled;
+        self
+    }
+
+    pub fn profile(mut self, profile: bool) -> Self {
+        self.config.profile = profile;
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.config.color = color;
+        self
+    }
+
+    pub fn collapse_name_whitespace(mut self, enabled: bool) -> Self {
+        self.config.collapse_name_whitespace = enabled;
+        self
+    }
+
+    pub fn skip_enrichment(mut self, skip_enrichment: bool) -> Self {
+        self.config.skip_enrichment = skip_enrichment;
+        self
+    }
+
+    pub fn provenance(mut self, provenance: Provenance) -> Self {
+        self.config.provenance = Some(provenance);
+        self
+    }
+
+    /// Register a custom rule; it runs after the built-in checks and earlier custom rules.
+    pub fn rule(mut self, rule: impl ValidationRule + 'static) -> Self {
+        self.config.custom_rules.push(Arc::new(rule));
+        self
+    }
+
+    /// Register a custom enricher; it runs after the built-in ones and earlier custom enrichers.
+    pub fn enricher(mut self, enricher: impl Enricher + 'static) -> Self {
+        self.config.custom_enrichers.push(Arc::new(enricher));
+        self
+    }
+
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.config.output_format = format;
+        self
+    }
+
+    pub fn template(mut self, template: OutputTemplate) -> Self {
+        self.config.template = Some(template);
+        self
+    }
+
+    pub fn parse_options(mut self, parse: ParseOptions) -> Self {
+        self.config.parse = parse;
+        self
+    }
+
+    /// Validates the accumulated settings and returns the finished config.
+    pub fn build(self) -> Result<ValidationConfig, PipelineError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
 static STRICT_EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}$")
         .expect("strict email regex must be valid")
 });
 
+static STRICT_IDN_EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^[A-Za-z0-9._%+-]+@(?:[\p{L}\p{N}](?:[\p{L}\p{N}-]*[\p{L}\p{N}])?\.)+\p{L}{2,}$")
+        .expect("strict IDN email regex must be valid")
+});
+
+/// Upgrade a config object from `version` to `CONFIG_VERSION`, one version at a time.
+fn migrate_config(config: &mut Map<String, Value>, version: u32) {
+    if version < 2 {
+        // Version 1 fields carry over unchanged; only the version marker is new.
+        config.insert("config_version".into(), Value::from(2));
+    }
+}
+
+/// Dotted paths of keys in `config` that have no counterpart in `known`. Only nested structs
+/// (objects that are non-empty in `known`) are searched; maps such as `label_overrides` are not.
+fn unknown_keys(config: &Map<String, Value>, known: &Value, prefix: &str) -> Vec<String> {
+    let mut unknown = Vec::new();
+    for (key, value) in config {
+        let path = format!("{prefix}{key}");
+        match (known.get(key), value) {
+            (None, _) => unknown.push(path),
+            (Some(Value::Object(fields)), Value::Object(nested)) if !fields.is_empty() => {
+                unknown.extend(unknown_keys(nested, &known[key], &format!("{path}.")));
+            }
+            _ => {}
+        }
+    }
+    unknown
+}
+
+/// Default configs used by `is_valid_email`, built once instead of per call.
+static LAX_EMAIL_CONFIG: Lazy<ValidationConfig> = Lazy::new(ValidationConfig::default);
+static STRICT_EMAIL_CONFIG: Lazy<ValidationConfig> = Lazy::new(|| ValidationConfig {
+    strict_email: true,
+    ..ValidationConfig::default()
+});
+
 /// Validates an email address according to the configured strictness level.
 pub fn is_valid_email(email: &str, strict: bool) -> bool {
+    let cfg = if strict {
+        &STRICT_EMAIL_CONFIG
+    } else {
+        &LAX_EMAIL_CONFIG
+    };
+    is_valid_email_with(email, cfg)
+}
+
+/// Validates an email address using every email-related option in `cfg`.
+///
+/// ```
+/// use monadic_pipeline::validation::is_valid_email_with;
+/// use monadic_pipeline::ValidationConfig;
+///
+/// let mut cfg = ValidationConfig { strict_email: true, ..ValidationConfig::default() };
+/// assert!(!is_valid_email_with("user@münchen.de", &cfg));
+/// cfg.allow_idn = true;
+/// assert!(is_valid_email_with("user@münchen.de", &cfg));
+/// ```
+pub fn is_valid_email_with(email: &str, cfg: &ValidationConfig) -> bool {
+    validate_email_detailed(email, cfg).is_ok()
+}
+
+/// Longest local part (before `@`) accepted in strict mode, per RFC 5321.
+const MAX_LOCAL_PART_LEN: usize = 64;
+
+/// Like [`is_valid_email_with`], but reports the first rule the address breaks.
+///
+/// ```
+/// use monadic_pipeline::validation::validate_email_detailed;
+/// use monadic_pipeline::{EmailError, ValidationConfig};
+///
+/// let cfg = ValidationConfig::default();
+/// assert_eq!(validate_email_detailed("alice.example.com", &cfg), Err(EmailError::MissingAt));
+/// assert_eq!(validate_email_detailed("alice@localhost", &cfg), Err(EmailError::MissingDomainDot));
+/// assert!(validate_email_detailed("alice@example.com", &cfg).is_ok());
+/// ```
+pub fn validate_email_detailed(email: &str, cfg: &ValidationConfig) -> Result<(), EmailError> {
     let candidate = email.trim();
     if candidate.is_empty() {
-        return false;
+        return Err(EmailError::Empty);
+    }
+
+    let mut parts = candidate.split('@');
+    let (local, domain) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => (local, domain),
+        (_, None, _) => return Err(EmailError::MissingAt),
+        _ => return Err(EmailError::MultipleAt),
+    };
+    if local.is_empty() {
+        return Err(EmailError::EmptyLocal);
+    }
+    if domain.is_empty() {
+        return Err(EmailError::EmptyDomain);
+    }
+    let Some((_, tld)) = domain.rsplit_once('.') else {
+        return Err(EmailError::MissingDomainDot);
+    };
+    if let Some(known) = &cfg.valid_tlds {
+        if !known.contains(&tld.to_lowercase()) {
+            return Err(EmailError::UnknownTld);
+        }
     }
 
-    if strict {
-        STRICT_EMAIL_REGEX.is_match(candidate)
+    if cfg.strict_email {
+        if local.chars().count() > MAX_LOCAL_PART_LEN {
+            return Err(EmailError::LocalTooLong);
+        }
+        let regex = if cfg.allow_idn {
+            &STRICT_IDN_EMAIL_REGEX
+        } else {
+            &STRICT_EMAIL_REGEX
+        };
+        if !regex.is_match(candidate) {
+            return Err(EmailError::RegexMismatch);
+        }
     } else {
-        let mut parts = candidate.split('@');
-        match (parts.next(), parts.next(), parts.next()) {
-            (Some(local), Some(domain), None) => {
-                !local.is_empty() && !domain.is_empty() && domain.contains('.')
-            }
-            _ => false,
+        let labels = domain.split('.').filter(|label| !label.is_empty()).count();
+        if labels < usize::from(cfg.min_domain_labels) {
+            return Err(EmailError::TooFewDomainLabels {
+                min: cfg.min_domain_labels,
+            });
+        }
+        if tld.chars().count() < usize::from(cfg.min_tld_len) {
+            return Err(EmailError::TldTooShort {
+                min: cfg.min_tld_len,
+            });
         }
     }
+    Ok(())
 }
@@ -2,9 +2,16 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 use std::error::Error;
 
+/// The binary's `run` subcommand, which the pipeline tests target.
+fn run_cmd() -> Result<Command, Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin("monadic-pipeline")?;
+    cmd.arg("run");
+    Ok(cmd)
+}
+
 #[test]
 fn cli_processes_stdin() -> Result<(), Box<dyn Error>> {
-    Command::cargo_bin("monadic-pipeline")?
+    run_cmd()?
         .arg("--in")
         .arg("-")
         .arg("--strict-email")
@@ -19,7 +26,7 @@ fn cli_processes_stdin() -> Result<(), Box<dyn Error>> {
 
 #[test]
 fn cli_reports_validation_error() -> Result<(), Box<dyn Error>> {
-    Command::cargo_bin("monadic-pipeline")?
+    run_cmd()?
         .arg("--in")
         .arg("-")
         .arg("--min-age")
@@ -27,14 +34,15 @@ fn cli_reports_validation_error() -> Result<(), Box<dyn Error>> {
         .write_stdin("Alice,30,alice@example.com\n")
         .assert()
         .failure()
-        .stderr(predicate::str::contains("below configured minimum"));
+        .stderr(predicate::str::contains("below configured minimum"))
+        .stderr(predicate::str::contains("hint: lower --min-age"));
     Ok(())
 }
 
 #[test]
 fn cli_reads_from_file() -> Result<(), Box<dyn Error>> {
     let fixture = std::path::Path::new("tests/data/users.csv");
-    Command::cargo_bin("monadic-pipeline")?
+    run_cmd()?
         .arg("--in")
         .arg(fixture)
         .assert()
@@ -43,3 +51,1119 @@ fn cli_reads_from_file() -> Result<(), Box<dyn Error>> {
         .stdout(predicate::str::contains("Bob (45, 40s)"));
     Ok(())
 }
+
+#[test]
+fn cli_strips_utf8_bom_from_file() -> Result<(), Box<dyn Error>> {
+    let fixture = std::path::Path::new("tests/data/users_bom.csv");
+    run_cmd()?
+        .arg("--in")
+        .arg(fixture)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Alice (30, 30s) -> username=alice\n",
+        ))
+        .stdout(predicate::str::contains("Bob (45, 40s)"));
+    Ok(())
+}
+
+#[test]
+fn cli_limit_processes_first_lines_only() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .arg("--in")
+        .arg("-")
+        .arg("--limit")
+        .arg("1")
+        .write_stdin("Alice,30,alice@example.com\nBob,45,bob@example.com\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Alice (30, 30s)"))
+        .stdout(predicate::str::contains("Bob").not());
+    Ok(())
+}
+
+#[test]
+fn cli_limit_zero_produces_no_output() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .arg("--in")
+        .arg("-")
+        .arg("--limit")
+        .arg("0")
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("username=").not());
+    Ok(())
+}
+
+#[test]
+fn cli_concatenates_repeated_inputs_in_order() -> Result<(), Box<dyn Error>> {
+    let fixture = std::path::Path::new("tests/data/users.csv");
+    run_cmd()?
+        .arg("--in")
+        .arg("-")
+        .arg("--in")
+        .arg(fixture)
+        .write_stdin("Carol,52,carol@example.com\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(
+            r"(?s)Carol \(52, 50\+\).*Alice \(30, 30s\).*Bob \(45, 40s\)",
+        )?);
+    Ok(())
+}
+
+#[test]
+fn cli_rejects_stdin_passed_twice() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "-", "--in", "-"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("at most once"));
+    Ok(())
+}
+
+#[test]
+fn cli_renders_custom_template() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "-", "--template", "{username}:{age_group}"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("alice:30s"));
+    Ok(())
+}
+
+#[test]
+fn cli_rejects_unknown_template_placeholder_at_startup() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "-", "--template", "{nickname}"])
+        .write_stdin("not even a valid line\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown template placeholder"));
+    Ok(())
+}
+
+#[test]
+fn cli_count_prints_only_number_of_records() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "-", "--count", "--limit", "2"])
+        .write_stdin(
+            "Alice,30,alice@example.com\nBob,45,bob@example.com\nCarol,52,carol@example.com\n",
+        )
+        .assert()
+        .success()
+        .stdout("2\n");
+    Ok(())
+}
+
+#[test]
+fn cli_count_with_continue_on_error_prints_the_failure_count_too() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["-q", "--count", "--continue-on-error"])
+        .write_stdin("Alice,30,alice@example.com\nBad,x,bad\nBob,45,bob@example.com\n")
+        .assert()
+        .code(5)
+        .stdout("2\n1\n");
+    Ok(())
+}
+
+#[test]
+fn cli_skips_comment_lines() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "-", "--comment-char", "#"])
+        .write_stdin("# exported users\nAlice,30,alice@example.com\n  # trailing note\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Alice (30, 30s)"));
+    Ok(())
+}
+
+#[test]
+fn cli_directory_output_matches_sequential_file_order() -> Result<(), Box<dyn Error>> {
+    let dir = std::path::Path::new("tests/data/multi");
+    let from_directory = run_cmd()?
+        .arg("--in")
+        .arg(dir)
+        .args(["--parallel", "4"])
+        .output()?;
+    assert!(from_directory.status.success());
+
+    let mut sequential = run_cmd()?;
+    for file in ["01_first.csv", "02_second.txt", "03_third.csv"] {
+        sequential.arg("--in").arg(dir.join(file));
+    }
+    let from_files = sequential.output()?;
+    assert!(from_files.status.success());
+
+    assert_eq!(from_directory.stdout, from_files.stdout);
+    let stdout = String::from_utf8(from_directory.stdout)?;
+    let names: Vec<_> = stdout
+        .lines()
+        .filter_map(|line| line.split(' ').next())
+        .collect();
+    assert_eq!(names, ["Alice", "Bob", "Carol", "Dave", "Erin"]);
+    Ok(())
+}
+
+#[test]
+fn cli_deny_warnings_fails_on_unsupported_directory_file() -> Result<(), Box<dyn Error>> {
+    // `tests/data/multi/README.md` is skipped with a warning in a normal run.
+    run_cmd()?
+        .args(["--in", "tests/data/multi"])
+        .assert()
+        .success();
+    run_cmd()?
+        .args(["--in", "tests/data/multi", "--deny-warnings"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(
+            predicate::str::contains("unsupported file").and(predicate::str::contains("README.md")),
+        );
+    Ok(())
+}
+
+#[test]
+fn cli_deny_warnings_fails_on_any_logged_warning() -> Result<(), Box<dyn Error>> {
+    let config =
+        std::env::temp_dir().join(format!("monadic-deny-config-{}.json", std::process::id()));
+    std::fs::write(
+        &config,
+        r#"{"config_version": 2, "shiny_new_option": true}"#,
+    )?;
+    let input = "Alice,30,alice@example.com\n";
+    run_cmd()?
+        .arg("--config")
+        .arg(&config)
+        .write_stdin(input)
+        .assert()
+        .success();
+    run_cmd()?
+        .arg("--config")
+        .arg(&config)
+        .args(["--deny-warnings", "--quiet"])
+        .write_stdin(input)
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("denied by --deny-warnings"));
+    std::fs::remove_file(&config)?;
+
+    run_cmd()?
+        .args(["--duplicate-emails", "warn", "--deny-warnings"])
+        .write_stdin("Alice,30,alice@example.com\nAl,31,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(
+            predicate::str::contains("duplicate email address")
+                .and(predicate::str::contains("denied by --deny-warnings")),
+        );
+    Ok(())
+}
+
+#[test]
+fn cli_with_provenance_wraps_records_with_meta() -> Result<(), Box<dyn Error>> {
+    let output = run_cmd()?
+        .args(["--in", "-", "--with-provenance", "--min-age", "18"])
+        .write_stdin("Alice,30,alice@example.com\nBob,45,bob@example.com\n")
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let records: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["record"]["user"]["name"], "Alice");
+    assert_eq!(records[0]["record"]["username"], "alice");
+
+    let meta = &records[0]["meta"];
+    let hash = meta["config_hash"].as_str().expect("config_hash");
+    assert_eq!(hash.len(), 64);
+    assert!(hash.bytes().all(|b| b.is_ascii_hexdigit()));
+    assert_eq!(
+        meta["rules"],
+        serde_json::json!(["NonEmptyName", "AgeBounds", "EmailFormat"])
+    );
+    assert!(meta["timestamp"].as_u64().is_some_and(|ts| ts > 0));
+    assert_eq!(records[1]["meta"], *meta);
+    Ok(())
+}
+
+#[test]
+fn cli_reads_fixed_width_records() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args([
+            "--in",
+            "tests/data/fixed_width.txt",
+            "--input-format",
+            "fixed",
+        ])
+        .args(["--widths", "20,3,37"])
+        .assert()
+        .success()
+        .stdout(
+            "Alice (30, 30s) -> username=alice\n\
+             Bob (45, 40s) -> username=bob\n\
+             Carol Danvers (38, 30s) -> username=caroldanvers\n",
+        );
+
+    run_cmd()?
+        .args([
+            "--in",
+            "-",
+            "--input-format",
+            "fixed",
+            "--widths",
+            "20,3,37",
+        ])
+        .write_stdin("Alice               30\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected at least 24"));
+    Ok(())
+}
+
+#[test]
+fn cli_filters_records_by_age_and_domain() -> Result<(), Box<dyn Error>> {
+    let input = "Alice,30,alice@example.com\nTim,12,tim@example.com\nBob,45,bob@other.org\n";
+    run_cmd()?
+        .args(["--in", "-", "--filter-age-min", "30"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("Alice (30, 30s) -> username=alice\nBob (45, 40s) -> username=bob\n");
+    run_cmd()?
+        .args([
+            "--in",
+            "-",
+            "--filter-age-min",
+            "30",
+            "--filter-domain",
+            "example.com",
+        ])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("Alice (30, 30s) -> username=alice\n");
+    Ok(())
+}
+
+#[test]
+fn cli_with_hash_appends_record_hash() -> Result<(), Box<dyn Error>> {
+    let output = run_cmd()?
+        .args(["--in", "-", "--with-hash"])
+        .write_stdin(
+            "Alice,30,alice@example.com\nalice,30,ALICE@example.com\nBob,45,bob@example.com\n",
+        )
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let hashes: Vec<_> = stdout
+        .lines()
+        .map(|line| line.rsplit_once(" hash=").expect("hash column").1)
+        .collect();
+    assert_eq!(hashes.len(), 3);
+    assert_eq!(hashes[0], hashes[1]);
+    assert_ne!(hashes[0], hashes[2]);
+    assert!(hashes.iter().all(|hash| hash.len() == 16));
+    Ok(())
+}
+
+#[test]
+fn cli_reads_tsv_with_quoted_tabs() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "-", "--delimiter", "tab", "--has-header"])
+        .write_stdin("email\tname\tage\nalice@example.com\t\"Alice\tA.\"\t30\n")
+        .assert()
+        .success()
+        .stdout("Alice\tA. (30, 30s) -> username=alicea\n");
+    Ok(())
+}
+
+#[cfg(feature = "schema")]
+#[test]
+fn cli_prints_schema_without_reading_input() -> Result<(), Box<dyn Error>> {
+    let output = Command::cargo_bin("monadic-pipeline")?
+        .arg("schema")
+        .write_stdin("this is not valid input\n")
+        .output()?;
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert!(schema["User"]["properties"]["email"].is_object());
+    assert!(schema["EnrichedUser"]["properties"]["age_group"].is_object());
+    Ok(())
+}
+
+/// Serves a single HTTP response on a local port and returns its URL.
+#[cfg(feature = "http")]
+fn serve_once(status: &'static str, body: &'static str) -> Result<String, Box<dyn Error>> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/users.csv", listener.local_addr()?);
+    std::thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    Ok(url)
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn cli_reads_input_from_url() -> Result<(), Box<dyn Error>> {
+    let url = serve_once(
+        "200 OK",
+        "Alice,30,alice@example.com\nBob,45,bob@example.com\n",
+    )?;
+    run_cmd()?
+        .arg("--in")
+        .arg(&url)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Alice (30, 30s)"))
+        .stdout(predicate::str::contains("Bob (45, 40s)"));
+
+    let url = serve_once("404 Not Found", "missing")?;
+    run_cmd()?
+        .arg("--in")
+        .arg(&url)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("returned HTTP 404"));
+    Ok(())
+}
+
+#[cfg(not(feature = "http"))]
+#[test]
+fn cli_rejects_url_input_without_http_feature() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .arg("--in")
+        .arg("https://example.com/users.csv")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("HTTP input support is disabled"));
+    Ok(())
+}
+
+#[test]
+fn cli_prints_shell_completions() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("monadic-pipeline")?
+        .arg("completions")
+        .arg("bash")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("complete -F"))
+        .stdout(predicate::str::contains("--min-age"));
+    Ok(())
+}
+
+#[test]
+fn cli_runs_pipeline_without_a_subcommand() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("monadic-pipeline")?
+        .args(["--min-age", "18", "--quiet"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .success()
+        .stdout("Alice (30, 30s) -> username=alice\n");
+
+    Command::cargo_bin("monadic-pipeline")?
+        .args(["--min-age", "18", "gen"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn cli_honours_line_ending_options() -> Result<(), Box<dyn Error>> {
+    let input = "Alice,30,alice@example.com\nBob,45,bob@example.com\n";
+    let cases: [(&[&str], &[u8]); 3] = [
+        (
+            &[],
+            b"Alice (30, 30s) -> username=alice\nBob (45, 40s) -> username=bob\n",
+        ),
+        (
+            &["--line-ending", "crlf"],
+            b"Alice (30, 30s) -> username=alice\r\nBob (45, 40s) -> username=bob\r\n",
+        ),
+        (
+            &["--line-ending", "crlf", "--no-trailing-newline"],
+            b"Alice (30, 30s) -> username=alice\r\nBob (45, 40s) -> username=bob",
+        ),
+    ];
+    for (args, expected) in cases {
+        let output = run_cmd()?.args(args).write_stdin(input).output()?;
+        assert!(output.status.success());
+        assert_eq!(output.stdout, expected, "args: {args:?}");
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_prints_age_histogram_to_stderr() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .arg("--histogram")
+        .env("COLUMNS", "20")
+        .write_stdin(
+            "Alice,30,alice@example.com\nBob,45,bob@example.com\nCarol,38,carol@example.com\n",
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Alice (30, 30s)"))
+        .stderr(predicate::str::contains(
+            "30-39 | ########## 2\n40-49 | ##### 1\n",
+        ));
+
+    run_cmd()?
+        .arg("--histogram")
+        .write_stdin("")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("no data"));
+    Ok(())
+}
+
+#[test]
+fn cli_gen_is_reproducible_for_a_seed() -> Result<(), Box<dyn Error>> {
+    let run = |seed: &str| -> Result<Vec<u8>, Box<dyn Error>> {
+        let output = Command::cargo_bin("monadic-pipeline")?
+            .args(["gen", "--count", "5", "--seed", seed])
+            .output()?;
+        assert!(output.status.success());
+        Ok(output.stdout)
+    };
+    let first = run("42")?;
+    assert_eq!(String::from_utf8(first.clone())?.lines().count(), 5);
+    assert_eq!(first, run("42")?);
+    assert_ne!(first, run("43")?);
+    Ok(())
+}
+
+#[test]
+fn cli_quiet_suppresses_tracing_output() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .arg("--quiet")
+        .env("RUST_LOG", "debug")
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Alice (30, 30s)"))
+        .stderr(predicate::str::is_empty());
+
+    run_cmd()?
+        .args(["--quiet", "--min-age", "40"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("below configured minimum"))
+        .stderr(predicate::str::contains("INFO").not());
+    Ok(())
+}
+
+#[test]
+fn cli_known_tlds_rejects_unlisted_domains() -> Result<(), Box<dyn Error>> {
+    let fixture = std::path::Path::new("tests/data/known_tlds.txt");
+    run_cmd()?
+        .arg("--known-tlds")
+        .arg(fixture)
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .success();
+
+    run_cmd()?
+        .arg("--known-tlds")
+        .arg(fixture)
+        .write_stdin("Alice,30,alice@foo.zzz\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "top-level domain is not in the known list",
+        ));
+    Ok(())
+}
+
+#[test]
+fn cli_continue_on_error_reports_partial_success() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .arg("--continue-on-error")
+        .write_stdin(
+            "Alice,30,alice@example.com\nBob,abc,bob@example.com\nCarol,38,carol@example.com\n",
+        )
+        .assert()
+        .code(5)
+        .stdout(predicate::str::contains("Alice (30, 30s)"))
+        .stdout(predicate::str::contains("Carol (38, 30s)"))
+        .stdout(predicate::str::contains("Bob").not())
+        .stderr(predicate::str::contains("processed 2, failed 1"));
+
+    run_cmd()?
+        .arg("--continue-on-error")
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("processed 1, failed 0"));
+
+    run_cmd()?
+        .arg("--continue-on-error")
+        .write_stdin("Bob,abc,bob@example.com\n")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("processed 0, failed 1"));
+    Ok(())
+}
+
+#[test]
+fn cli_max_error_rate_aborts_mostly_invalid_input() -> Result<(), Box<dyn Error>> {
+    let mut input = String::new();
+    for i in 0..30 {
+        if i % 3 == 0 {
+            input.push_str(&format!("User{i},30,user{i}@example.com\n"));
+        } else {
+            input.push_str(&format!("User{i},abc,user{i}@example.com\n"));
+        }
+    }
+    run_cmd()?
+        .args(["--continue-on-error", "--max-error-rate", "0.2"])
+        .write_stdin(input.clone())
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains(
+            "13 of 20 lines were rejected, exceeding the maximum error rate",
+        ));
+
+    run_cmd()?
+        .args(["--continue-on-error", "--max-error-rate", "0.7"])
+        .write_stdin(input)
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains("processed 10, failed 20"));
+
+    run_cmd()?
+        .args(["--max-error-rate", "1.5"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "max_error_rate 1.5 is outside 0.0..=1.0",
+        ));
+    Ok(())
+}
+
+#[test]
+fn cli_buckets_file_groups_ages_by_custom_ranges() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--buckets-file", "tests/data/buckets.csv"])
+        .write_stdin("Alice,30,alice@example.com\nBob,40,bob@example.com\nCid,12,cid@example.com\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Alice (30, young adult)"))
+        .stdout(predicate::str::contains("Bob (40, unspecified)"))
+        .stdout(predicate::str::contains("Cid (12, minor)"));
+
+    run_cmd()?
+        .args(["--buckets-file", "tests/data/buckets_overlapping.json"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "age buckets `minor` (0-17) and `youth` (15-30) overlap",
+        ));
+    Ok(())
+}
+
+#[test]
+fn cli_strict_blank_lines_reports_the_input_line_past_comments() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--strict-blank-lines", "--comment-char", "#"])
+        .write_stdin("# c\n\nb,1,b@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 2 is blank"));
+    Ok(())
+}
+
+#[test]
+fn cli_strict_enables_the_strict_bundle() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .arg("--strict")
+        .write_stdin("Alice,30,alice@example.com\n\nBob,40,bob@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 2 is blank"));
+
+    run_cmd()?
+        .arg("--strict")
+        .write_stdin("Alice,30,alice@example.com\nAlice,31,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("duplicate"));
+
+    run_cmd()?
+        .args(["--strict", "--duplicate-emails", "allow"])
+        .write_stdin("Alice,30,alice@example.com\nAlice,31,alice@example.com\n")
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn cli_profile_prints_stage_table() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--profile", "--quiet"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .success()
+        .stdout("Alice (30, 30s) -> username=alice\n")
+        .stderr(predicate::str::contains("validate"))
+        .stderr(predicate::str::contains("total"));
+    Ok(())
+}
+
+#[test]
+fn cli_csv_format_quotes_fields() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--format", "csv", "--quiet"])
+        .write_stdin("\"Doe, Jane\",30,jane@example.com\n")
+        .assert()
+        .success()
+        .stdout("\"Doe, Jane\",30,jane@example.com,doejane,30s\n");
+    Ok(())
+}
+
+#[test]
+fn cli_no_enrich_passes_valid_rows_through() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--no-enrich", "--continue-on-error", "--quiet"])
+        .write_stdin(
+            "  Alice ,30, alice@example.com\n\"Doe, Jane\",41,jane@example.com\nBad,abc,bad@example.com\n",
+        )
+        .assert()
+        .code(5)
+        .stdout("Alice,30,alice@example.com\n\"Doe, Jane\",41,jane@example.com\n");
+    Ok(())
+}
+
+#[test]
+fn cli_rejects_lines_over_max_line_len() -> Result<(), Box<dyn Error>> {
+    let long_name = "A".repeat(64);
+    run_cmd()?
+        .args(["--max-line-len", "48"])
+        .write_stdin(format!(
+            "Alice,30,alice@example.com\n{long_name},30,long@example.com\n"
+        ))
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("line 2 is longer than 48 bytes"));
+
+    run_cmd()?
+        .args(["--max-line-len", "48", "--quiet"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn cli_piped_output_has_no_color_codes() -> Result<(), Box<dyn Error>> {
+    for args in [&["--quiet"][..], &["--quiet", "--no-color"]] {
+        run_cmd()?
+            .args(args)
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE")
+            .write_stdin("Alice,30,alice@example.com\n")
+            .assert()
+            .success()
+            .stdout("Alice (30, 30s) -> username=alice\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn cli_reads_reordered_columns() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--columns", "email,name,age", "--quiet"])
+        .write_stdin("alice@example.com,Alice,30\n")
+        .assert()
+        .success()
+        .stdout("Alice (30, 30s) -> username=alice\n");
+
+    run_cmd()?
+        .args(["--has-header", "--quiet"])
+        .write_stdin("Email,Age,Name\nbob@example.com,45,Bob\n")
+        .assert()
+        .success()
+        .stdout("Bob (45, 40s) -> username=bob\n");
+
+    run_cmd()?
+        .args(["--columns", "name,age"])
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected 3 columns"));
+    Ok(())
+}
+
+#[test]
+fn cli_continue_on_error_isolates_unreadable_files_in_directory() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "tests/data/mixed"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("02_malformed.csv"));
+
+    run_cmd()?
+        .args(["--in", "tests/data/mixed", "--continue-on-error", "--quiet"])
+        .assert()
+        .code(5)
+        .stdout(
+            "Alice (30, 30s) -> username=alice\n\
+             Carol (38, 30s) -> username=carol\n\
+             Erin (29, 20s) -> username=erin\n",
+        )
+        .stderr(predicate::str::contains("processed 3, failed 1"))
+        .stderr(predicate::str::contains(
+            "01_good.csv: processed 2, failed 1",
+        ))
+        .stderr(predicate::str::contains("02_malformed.csv: unreadable"))
+        .stderr(predicate::str::contains(
+            "03_after.csv: processed 1, failed 0",
+        ));
+    Ok(())
+}
+
+#[test]
+fn cli_explain_prints_config_and_sources() -> Result<(), Box<dyn Error>> {
+    let output = run_cmd()?
+        .args(["--explain", "--quiet", "--min-age", "21", "--strict"])
+        .args(["--known-tlds", "tests/data/known_tlds.txt"])
+        .write_stdin("not,a,valid,line\n")
+        .output()?;
+    assert!(output.status.success());
+
+    let explained: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(explained["config"]["min_age"], 21);
+    assert_eq!(explained["config"]["strict_email"], true);
+    assert_eq!(explained["sources"]["min_age"], "cli");
+    assert_eq!(explained["sources"]["strict_email"], "cli");
+    assert_eq!(explained["sources"]["parse.empty_lines"], "cli");
+    assert_eq!(explained["sources"]["sort_by"], "default");
+    let tlds = explained["config"]["valid_tlds"]
+        .as_array()
+        .ok_or("valid_tlds is a list")?;
+    assert!(tlds.len() > 1);
+    assert!(
+        tlds.windows(2)
+            .all(|pair| pair[0].as_str() < pair[1].as_str()),
+        "valid_tlds is sorted: {tlds:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_validate_config_reports_ok_or_the_problem() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--config", "tests/data/config_v1.json", "--validate-config"])
+        .args(["--in", "tests/data/does-not-exist.csv"])
+        .assert()
+        .success()
+        .stdout("OK\n");
+
+    run_cmd()?
+        .args([
+            "--config",
+            "tests/data/config_invalid.json",
+            "--validate-config",
+        ])
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains(
+            "supported age ceiling 40 is below min_age 50",
+        ));
+
+    run_cmd()?
+        .args(["--validate-config"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--config"));
+    Ok(())
+}
+
+#[test]
+fn cli_config_file_is_overridden_by_flags() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--config", "tests/data/config_v1.json", "--quiet"])
+        .write_stdin("Alice,16,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("below configured minimum 18"));
+
+    run_cmd()?
+        .args([
+            "--config",
+            "tests/data/config_v1.json",
+            "--min-age",
+            "10",
+            "--quiet",
+        ])
+        .write_stdin("Alice,16,alice@example.com\n")
+        .assert()
+        .success();
+
+    let output = run_cmd()?
+        .args([
+            "--config",
+            "tests/data/config_v1.json",
+            "--min-age",
+            "10",
+            "--explain",
+        ])
+        .output()?;
+    let explained: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(explained["sources"]["min_age"], "cli");
+    assert_eq!(explained["sources"]["strict_email"], "file");
+    assert_eq!(explained["sources"]["profile"], "default");
+    Ok(())
+}
+
+#[test]
+fn cli_labels_file_translates_age_groups() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--labels", "tests/data/labels_de.json", "--quiet"])
+        .write_stdin("Anna,16,anna@example.de\nBen,42,ben@example.de\n")
+        .assert()
+        .success()
+        .stdout("Anna (16, Jugendliche) -> username=anna\nBen (42, 40er) -> username=ben\n");
+    Ok(())
+}
+
+#[test]
+fn cli_skip_unchanged_reuses_previous_output() -> Result<(), Box<dyn Error>> {
+    let dir = std::env::temp_dir().join(format!("monadic-skip-unchanged-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let input = dir.join("users.csv");
+    let output = dir.join("out.txt");
+    std::fs::write(&input, "Alice,30,alice@example.com\n")?;
+
+    let run = || -> Result<assert_cmd::assert::Assert, Box<dyn Error>> {
+        Ok(run_cmd()?
+            .arg("--in")
+            .arg(&input)
+            .arg("--out")
+            .arg(&output)
+            .args(["--skip-unchanged", "--quiet"])
+            .assert()
+            .success())
+    };
+
+    run()?.stderr(predicate::str::contains("skipping").not());
+    assert!(dir.join("out.txt.hash").exists());
+    run()?.stderr(predicate::str::contains("input unchanged"));
+
+    // Flags that change the output invalidate the fingerprint too.
+    run_cmd()?
+        .arg("--in")
+        .arg(&input)
+        .arg("--out")
+        .arg(&output)
+        .args(["--skip-unchanged", "--quiet", "--count"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("skipping").not());
+    assert_eq!(std::fs::read_to_string(&output)?, "1\n");
+
+    std::fs::write(&input, "Bob,45,bob@example.com\n")?;
+    run()?.stderr(predicate::str::contains("skipping").not());
+    assert_eq!(
+        std::fs::read_to_string(&output)?,
+        "Bob (45, 40s) -> username=bob\n"
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn cli_directory_files_are_read_in_byte_order() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--in", "tests/data/case_order", "--quiet"])
+        .assert()
+        .success()
+        .stdout(
+            "Bea (22, 20s) -> username=bea\n\
+             Amy (21, 20s) -> username=amy\n\
+             Cy (23, 20s) -> username=cy\n",
+        );
+    Ok(())
+}
+
+#[test]
+fn cli_errors_out_writes_json_error_records() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("monadic-errors-{}.ndjson", std::process::id()));
+    run_cmd()?
+        .args([
+            "--continue-on-error",
+            "--min-age",
+            "18",
+            "--quiet",
+            "--errors-out",
+        ])
+        .arg(&path)
+        .write_stdin("Alice,30,alice@example.com\nKid,5,kid@example.com\n")
+        .assert()
+        .code(5)
+        .stdout("Alice (30, 30s) -> username=alice\n");
+
+    let written = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    let records: Vec<serde_json::Value> = written
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(
+        records,
+        [serde_json::json!({
+            "line": 2,
+            "error": "age 5 is below configured minimum 18",
+            "kind": "InvalidAge",
+            "age": 5,
+            "min_age": 18,
+        })]
+    );
+    Ok(())
+}
+
+#[test]
+fn cli_errors_out_reports_input_line_numbers_past_comments() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!(
+        "monadic-errors-lines-{}.ndjson",
+        std::process::id()
+    ));
+    run_cmd()?
+        .args([
+            "--continue-on-error",
+            "--comment-char",
+            "#",
+            "--quiet",
+            "--errors-out",
+        ])
+        .arg(&path)
+        .write_stdin("# users\n\nAlice,30,alice@example.com\nBob,x,bob@example.com\n")
+        .assert()
+        .code(5)
+        .stdout("Alice (30, 30s) -> username=alice\n");
+
+    let written = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    let record: serde_json::Value = serde_json::from_str(written.trim_end())?;
+    assert_eq!(record["line"], 4);
+    Ok(())
+}
+
+#[test]
+fn cli_with_line_text_reports_masked_rejected_lines() -> Result<(), Box<dyn Error>> {
+    let path =
+        std::env::temp_dir().join(format!("monadic-line-text-{}.ndjson", std::process::id()));
+    run_cmd()?
+        .args(["--continue-on-error", "--with-line-text", "--errors-out"])
+        .arg(&path)
+        .write_stdin("Alice,30,alice@example.com\nKid,abc,kid@example.com\n")
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains("Kid,abc,k***@example.com"))
+        .stderr(predicate::str::contains("kid@example.com").not());
+
+    let written = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    let record: serde_json::Value = serde_json::from_str(written.trim_end())?;
+    assert_eq!(record["line"], 2);
+    assert_eq!(record["kind"], "Parse");
+    assert_eq!(record["line_text"], "Kid,abc,k***@example.com");
+    Ok(())
+}
+
+#[test]
+fn cli_tail_emits_each_record_before_the_next_line_arrives() -> Result<(), Box<dyn Error>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("monadic-pipeline"))
+        .args(["run", "--tail"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("piped stdin");
+    let stdout = child.stdout.take().expect("piped stdout");
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let next_record = || rx.recv_timeout(Duration::from_secs(10));
+
+    writeln!(stdin, "Alice,30,alice@example.com")?;
+    assert_eq!(next_record()??, "Alice (30, 30s) -> username=alice");
+    std::thread::sleep(Duration::from_millis(100));
+    writeln!(stdin, "Bob,abc,bob@example.com")?;
+    writeln!(stdin, "Carol,38,carol@example.com")?;
+    assert_eq!(next_record()??, "Carol (38, 30s) -> username=carol");
+
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("invalid age `abc`"), "stderr: {stderr}");
+    assert!(stderr.contains("processed 2, failed 1"), "stderr: {stderr}");
+    Ok(())
+}
+
+#[test]
+fn cli_tail_honours_limit_and_rejects_unsupported_output_flags() -> Result<(), Box<dyn Error>> {
+    run_cmd()?
+        .args(["--tail", "--limit", "1"])
+        .write_stdin("Alice,30,alice@example.com\nBob,45,bob@example.com\n")
+        .assert()
+        .success()
+        .stdout("Alice (30, 30s) -> username=alice\n")
+        .stderr(predicate::str::contains("processed 1, failed 0"));
+
+    run_cmd()?
+        .args([
+            "--tail",
+            "--continue-on-error",
+            "--errors-out",
+            "errors.jsonl",
+        ])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    run_cmd()?
+        .args(["--tail", "--no-trailing-newline"])
+        .write_stdin("Alice,30,alice@example.com\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
@@ -1,10 +1,18 @@
-use monadic_pipeline::{process_line, process_lines, AgeGroupingMode, ValidationConfig};
+use monadic_pipeline::{
+    dedup_key, enrich, format_user, parse_and_validate, process_line, process_lines,
+    process_lines_enriched, process_lines_observed, process_lines_to_writer,
+    process_lines_with_progress, process_reader, run_pipeline, AgeGroupCounter, AgeGroupingMode,
+    DuplicateEmailPolicy, EmailError, EmptyLinePolicy, InputLine, InputSource, MemorySink,
+    MemorySource, OutputOptions, OutputSink, ParseOptions, PipelineError, ProcessSummary,
+    RejectedLine, RunOptions, SortKey, ValidationConfig, CONFIG_VERSION,
+};
 
 fn default_config() -> ValidationConfig {
     ValidationConfig {
         min_age: 0,
         strict_email: true,
         age_grouping: AgeGroupingMode::Default,
+        ..ValidationConfig::default()
     }
 }
 
@@ -33,6 +41,7 @@ fn process_lines_short_circuits_on_error() {
         min_age: 40,
         strict_email: true,
         age_grouping: AgeGroupingMode::Default,
+        ..ValidationConfig::default()
     };
     let inputs = vec![
         "Alice,30,alice@example.com".to_string(),
@@ -44,3 +53,627 @@ fn process_lines_short_circuits_on_error() {
         monadic_pipeline::PipelineError::InvalidAge { .. }
     ));
 }
+
+#[test]
+fn public_stages_allow_custom_logic_between_steps() {
+    let cfg = default_config();
+    let mut user = parse_and_validate(" bob , 45 , bob@example.com", &cfg).expect("valid");
+    assert_eq!(user.name, "bob");
+    user.name = "Robert".into();
+
+    let enriched = enrich(user, AgeGroupingMode::Wide);
+    assert_eq!(enriched.age_group.label(), "adult");
+    assert_eq!(enriched.username, "robert");
+}
+
+#[test]
+fn process_reader_filters_blank_and_comment_lines() {
+    let mut cfg = default_config();
+    cfg.parse.comment_char = Some('#');
+    let input = std::io::Cursor::new(
+        "# header comment\nAlice,30,alice@example.com  \r\n\n   \nBob,45,bob@example.com\n",
+    );
+    let outputs = process_reader(input, &cfg).expect("reader should process");
+    assert_eq!(
+        outputs,
+        vec![
+            "Alice (30, 30s) -> username=alice".to_string(),
+            "Bob (45, 40s) -> username=bob".to_string(),
+        ]
+    );
+}
+
+fn duplicate_inputs() -> Vec<String> {
+    vec![
+        "Alice,30,alice@example.com".to_string(),
+        "Alicia,31,Alice@Example.com".to_string(),
+    ]
+}
+
+#[test]
+fn duplicate_emails_allowed_by_default() {
+    let outputs = process_lines(duplicate_inputs(), &default_config()).expect("allowed");
+    assert_eq!(outputs.len(), 2);
+}
+
+#[test]
+fn duplicate_emails_warn_policy_keeps_going() {
+    let cfg = ValidationConfig {
+        duplicate_email_policy: DuplicateEmailPolicy::Warn,
+        ..default_config()
+    };
+    let outputs = process_lines(duplicate_inputs(), &cfg).expect("warn only");
+    assert_eq!(outputs.len(), 2);
+}
+
+#[test]
+fn duplicate_emails_error_policy_rejects_second_occurrence() {
+    let cfg = ValidationConfig {
+        duplicate_email_policy: DuplicateEmailPolicy::Error,
+        ..default_config()
+    };
+    let err = process_lines(duplicate_inputs(), &cfg).expect_err("duplicate should fail");
+    assert_eq!(
+        err,
+        monadic_pipeline::PipelineError::DuplicateEmail {
+            email: "A***@Example.com".into()
+        }
+    );
+}
+
+#[test]
+fn strip_plus_tags_dedups_tagged_addresses() {
+    let lines = vec![
+        "Alice,30,alice+newsletter@example.com".to_string(),
+        "Alicia,31,alice@example.com".to_string(),
+    ];
+    let cfg = ValidationConfig {
+        duplicate_email_policy: DuplicateEmailPolicy::Error,
+        ..default_config()
+    };
+    assert_eq!(
+        process_lines(lines.clone(), &cfg).expect("distinct").len(),
+        2
+    );
+
+    let cfg = ValidationConfig {
+        strip_plus_tags: true,
+        ..cfg
+    };
+    let err = process_lines(lines.clone(), &cfg).expect_err("same mailbox");
+    assert!(matches!(
+        err,
+        monadic_pipeline::PipelineError::DuplicateEmail { .. }
+    ));
+
+    let records = process_lines_enriched(lines[..1].to_vec(), &cfg).expect("valid");
+    assert_eq!(records[0].user.email, "alice+newsletter@example.com");
+}
+
+#[test]
+fn standard_typed_pipeline_matches_process_line() {
+    let cfg = default_config();
+    let pipeline = monadic_pipeline::pipeline::Pipeline::standard(&cfg);
+    for line in [
+        "Alice,30,alice@example.com",
+        "Bob,abc,bob@example.com",
+        "Carol,38,carol@",
+    ] {
+        assert_eq!(pipeline.execute(line), process_line(line, &cfg));
+    }
+}
+
+#[test]
+fn progress_is_reported_every_n_lines_and_at_the_end() {
+    let lines: Vec<String> = (0..2500)
+        .map(|i| {
+            if i % 100 == 99 {
+                format!("User{i},abc,user{i}@example.com")
+            } else {
+                format!("User{i},30,user{i}@example.com")
+            }
+        })
+        .collect();
+    let cfg = ValidationConfig {
+        continue_on_error: true,
+        ..default_config()
+    };
+    assert_eq!(cfg.report_every, 1000);
+
+    let mut reports = Vec::new();
+    let summary = process_lines_with_progress(
+        lines.clone(),
+        &cfg,
+        std::io::sink(),
+        &OutputOptions::default(),
+        |progress| reports.push(progress),
+    )
+    .expect("continue on error");
+    assert_eq!(summary.failed, 25);
+    let counts: Vec<_> = reports
+        .iter()
+        .map(|p| (p.lines, p.processed, p.failed))
+        .collect();
+    assert_eq!(
+        counts,
+        [(1000, 990, 10), (2000, 1980, 20), (2500, 2475, 25)]
+    );
+
+    let mut calls = 0;
+    let cfg = ValidationConfig {
+        report_every: 500,
+        ..cfg
+    };
+    process_lines_with_progress(
+        lines,
+        &cfg,
+        std::io::sink(),
+        &OutputOptions::default(),
+        |_| calls += 1,
+    )
+    .expect("continue on error");
+    assert_eq!(calls, 5);
+}
+
+#[test]
+fn record_filter_drops_non_matching_records_without_failing() {
+    let lines = vec![
+        "Alice,30,alice@example.com".to_string(),
+        "Tim,12,tim@example.com".to_string(),
+        "Bob,45,bob@other.org".to_string(),
+        "Carol,38,carol@EXAMPLE.com".to_string(),
+        "Dan,,dan@example.com".to_string(),
+    ];
+    let cfg = ValidationConfig {
+        require_age: false,
+        filter: monadic_pipeline::RecordFilter {
+            min_age: Some(30),
+            domain: Some("example.com".into()),
+        },
+        ..default_config()
+    };
+    let records = process_lines_enriched(lines.clone(), &cfg).expect("filtered");
+    let names: Vec<_> = records.iter().map(|r| r.user.name.as_str()).collect();
+    assert_eq!(names, ["Alice", "Carol"]);
+
+    let output = process_lines_observed(lines, &cfg, |_| {}).expect("filtered");
+    assert_eq!(output.lines.len(), 2);
+    assert_eq!((output.summary.processed, output.summary.failed), (5, 0));
+}
+
+#[test]
+fn dedup_key_matches_duplicate_detection() {
+    let cfg = default_config();
+    let keys: Vec<String> = duplicate_inputs()
+        .iter()
+        .chain(&["Al,32,  ALICE@example.COM".to_string()])
+        .map(|line| dedup_key(&parse_and_validate(line, &cfg).expect("valid line")))
+        .collect();
+    assert_eq!(keys, ["alice@example.com"; 3]);
+
+    let bob = parse_and_validate("Bob,45,bob@example.com", &cfg).unwrap();
+    assert_ne!(dedup_key(&bob), keys[0]);
+}
+
+#[test]
+fn process_lines_sorts_by_requested_field() {
+    let inputs = vec![
+        "Carol,52,carol@example.com".to_string(),
+        "Alice,30,alice@example.com".to_string(),
+        "Bob,30,bob@example.com".to_string(),
+    ];
+
+    let by_age = ValidationConfig {
+        sort_by: Some(SortKey::Age),
+        ..default_config()
+    };
+    let outputs = process_lines(inputs.clone(), &by_age).expect("sorted");
+    assert!(outputs[0].starts_with("Alice") && outputs[1].starts_with("Bob"));
+
+    let by_age_desc = ValidationConfig {
+        sort_desc: true,
+        ..by_age
+    };
+    let outputs = process_lines(inputs.clone(), &by_age_desc).expect("sorted");
+    // Stable: Alice and Bob tie on age and keep their input order.
+    assert!(outputs[0].starts_with("Carol") && outputs[1].starts_with("Alice"));
+
+    let by_name = ValidationConfig {
+        sort_by: Some(SortKey::Name),
+        ..default_config()
+    };
+    let outputs = process_lines(inputs, &by_name).expect("sorted");
+    assert!(outputs[2].starts_with("Carol"));
+}
+
+#[test]
+fn age_group_counter_tallies_records() {
+    let cfg = default_config();
+    let lines = [
+        "Alice,30,alice@example.com",
+        "Bob,45,bob@example.com",
+        "Carol,38,carol@example.com",
+        "Dave,17,dave@example.com",
+        "Erin,61,erin@example.com",
+    ];
+
+    let mut counter = AgeGroupCounter::new();
+    for line in lines {
+        let user = parse_and_validate(line, &cfg).expect("valid");
+        counter.record(&enrich(user, cfg.age_grouping));
+    }
+
+    assert_eq!(counter.total(), 5);
+    assert_eq!(
+        counter.into_report(),
+        vec![
+            ("30s".to_string(), 2),
+            ("40s".to_string(), 1),
+            ("teens".to_string(), 1),
+            ("50+".to_string(), 1),
+        ]
+    );
+}
+
+#[test]
+fn process_lines_enriched_returns_structured_records() {
+    let cfg = ValidationConfig {
+        sort_by: Some(SortKey::Age),
+        ..default_config()
+    };
+    let inputs = vec![
+        "Bob,45,bob@example.com".to_string(),
+        "Alice,30,alice@example.com".to_string(),
+    ];
+
+    let records = process_lines_enriched(inputs.clone(), &cfg).expect("processing should succeed");
+    let usernames: Vec<_> = records.iter().map(|r| r.username.as_str()).collect();
+    assert_eq!(usernames, ["alice", "bob"]);
+    assert_eq!(records[1].age_group.label(), "40s");
+
+    let formatted: Vec<_> = records.iter().map(format_user).collect();
+    assert_eq!(
+        formatted,
+        process_lines(inputs, &cfg).expect("processing should succeed")
+    );
+}
+
+#[test]
+fn synthetic_lines_are_deterministic_and_valid() {
+    let rows = monadic_pipeline::generate::synthetic_lines(200, 7);
+    assert_eq!(rows, monadic_pipeline::generate::synthetic_lines(200, 7));
+    assert_ne!(rows, monadic_pipeline::generate::synthetic_lines(200, 8));
+
+    let cfg = ValidationConfig {
+        duplicate_email_policy: DuplicateEmailPolicy::Error,
+        ..default_config()
+    };
+    let outputs = process_lines(rows, &cfg).expect("generated rows should pass validation");
+    assert_eq!(outputs.len(), 200);
+}
+
+#[test]
+fn blank_lines_are_skipped_by_default() {
+    let input = std::io::Cursor::new("Alice,30,alice@example.com\n   \nBob,45,bob@example.com\n");
+    let outputs = process_reader(input, &default_config()).expect("blank lines are skipped");
+    assert_eq!(outputs.len(), 2);
+}
+
+#[test]
+fn blank_lines_are_rejected_with_line_number_when_strict() {
+    let mut cfg = default_config();
+    cfg.parse.empty_lines = EmptyLinePolicy::Error;
+    let input = std::io::Cursor::new("Alice,30,alice@example.com\n   \nBob,45,bob@example.com\n");
+    let err = process_reader(input, &cfg).expect_err("blank line should fail");
+    assert_eq!(
+        err,
+        PipelineError::Parse {
+            reason: "line 2 is blank".into(),
+            column: None,
+        }
+    );
+}
+
+#[test]
+fn blank_line_errors_count_dropped_comment_lines() {
+    let mut cfg = default_config();
+    cfg.parse.empty_lines = EmptyLinePolicy::Error;
+    cfg.parse.comment_char = Some('#');
+    let input = std::io::Cursor::new("# header note\n# another\nAlice,30,alice@example.com\n\n");
+    let err = process_reader(input, &cfg).expect_err("blank line should fail");
+    assert_eq!(
+        err,
+        PipelineError::Parse {
+            reason: "line 4 is blank".into(),
+            column: None,
+        }
+    );
+}
+
+#[test]
+fn overlong_lines_fail_process_reader_with_a_parse_error() {
+    let mut cfg = default_config();
+    cfg.parse.max_line_len = 30;
+    let input = std::io::Cursor::new(
+        "Alice,30,alice@example.com\nBartholomew,45,bartholomew@example.com\n",
+    );
+    let err = process_reader(input, &cfg).expect_err("overlong line should fail");
+    assert_eq!(
+        err,
+        PipelineError::Parse {
+            reason: "line 2 is longer than 30 bytes".into(),
+            column: None,
+        }
+    );
+}
+
+#[test]
+fn process_lines_to_writer_stops_at_first_error() {
+    let cfg = default_config();
+    let inputs = vec![
+        "Alice,30,alice@example.com".to_string(),
+        "Bob,45,not-an-email".to_string(),
+        "Carol,38,carol@example.com".to_string(),
+    ];
+    let mut out = Vec::new();
+    let err = process_lines_to_writer(inputs, &cfg, &mut out, &OutputOptions::default())
+        .expect_err("second line is invalid");
+    assert!(matches!(err, PipelineError::InvalidEmail { .. }));
+    assert_eq!(out, b"Alice (30, 30s) -> username=alice\n");
+}
+
+#[test]
+fn memory_source_and_sink_round_trip() {
+    let cfg = default_config();
+    let source =
+        MemorySource::new("# header\nAlice,30,alice@example.com\n\nBob,45,bob@example.com\n");
+    let mut parse = cfg.parse.clone();
+    parse.comment_char = Some('#');
+    let lines = source.read_lines(&parse).expect("in-memory read");
+
+    let mut sink = MemorySink::new();
+    let summary = process_lines_to_writer(
+        lines,
+        &cfg,
+        sink.open().expect("in-memory sink"),
+        &OutputOptions::default(),
+    )
+    .expect("processing should succeed");
+    assert_eq!(summary.processed, 2);
+    assert_eq!(
+        sink.contents(),
+        b"Alice (30, 30s) -> username=alice\nBob (45, 40s) -> username=bob\n"
+    );
+}
+
+#[test]
+fn continue_on_error_skips_rejected_lines() {
+    let cfg = ValidationConfig {
+        continue_on_error: true,
+        ..default_config()
+    };
+    let inputs = vec![
+        "Alice,30,alice@example.com".to_string(),
+        "Bob,45,not-an-email".to_string(),
+        "Carol,38,carol@example.com".to_string(),
+    ];
+    let output = process_lines_observed(inputs.clone(), &cfg, |_| {}).expect("errors are skipped");
+    assert_eq!(output.lines.len(), 2);
+    assert_eq!(
+        output.summary,
+        ProcessSummary {
+            processed: 2,
+            failed: 1,
+            rejected_lines: vec![2],
+            rejections: vec![RejectedLine {
+                line: 2,
+                error: PipelineError::InvalidEmail {
+                    email: "***".into(),
+                    reason: EmailError::MissingAt,
+                },
+                text: None,
+            }],
+            timings: None,
+        }
+    );
+
+    let forgetful = ValidationConfig {
+        retain_rejections: false,
+        ..cfg
+    };
+    let output = process_lines_observed(inputs, &forgetful, |_| {}).expect("errors are skipped");
+    assert_eq!(output.summary.failed, 1);
+    assert!(output.summary.rejected_lines.is_empty());
+    assert!(output.summary.rejections.is_empty());
+
+    let loaded =
+        ValidationConfig::from_json(r#"{"config_version": 2, "retain_rejections": false}"#)
+            .expect("unknown keys are ignored");
+    assert!(
+        loaded.retain_rejections,
+        "retain_rejections is not loadable from a config file"
+    );
+}
+
+#[test]
+fn profile_reports_stage_timings_only_when_enabled() {
+    let inputs = vec![
+        "Alice,30,alice@example.com".to_string(),
+        "Bob,45,bob@example.com".to_string(),
+    ];
+    let plain = process_lines_observed(inputs.clone(), &default_config(), |_| {})
+        .expect("processing should succeed");
+    assert_eq!(plain.summary.timings, None);
+
+    let cfg = ValidationConfig {
+        profile: true,
+        ..default_config()
+    };
+    let profiled = process_lines_observed(inputs, &cfg, |_| {}).expect("processing should succeed");
+    assert_eq!(profiled.lines, plain.lines);
+    let timings = profiled.summary.timings.expect("profiling enabled");
+    assert_eq!(
+        timings.total(),
+        timings.parse + timings.validate + timings.enrich + timings.format
+    );
+}
+
+#[test]
+fn run_pipeline_reads_processes_and_writes_in_memory() {
+    let input = MemorySource::new(
+        "email,name,age\nalice@example.com,Alice,30\nbob@example.com,Bob,45\ncarol@example.com,Carol,38\n",
+    );
+    let opts = RunOptions {
+        has_header: true,
+        infer_columns: true,
+        limit: Some(2),
+        histogram: true,
+        ..RunOptions::default()
+    };
+    let mut output = MemorySink::new();
+    let report =
+        run_pipeline(&input, &mut output, &default_config(), &opts).expect("run should succeed");
+
+    assert_eq!(report.summary.processed, 2);
+    assert!(!report.interrupted);
+    assert_eq!(
+        output.contents(),
+        b"Alice (30, 30s) -> username=alice\nBob (45, 40s) -> username=bob\n"
+    );
+    let histogram = report.histogram.expect("histogram requested");
+    assert_eq!(
+        histogram.render(20),
+        "30-39 | ########## 1\n40-49 | ########## 1\n"
+    );
+
+    let counted = RunOptions {
+        count_only: true,
+        ..RunOptions::default()
+    };
+    let mut output = MemorySink::new();
+    let input = MemorySource::new("Alice,30,alice@example.com\n");
+    run_pipeline(&input, &mut output, &default_config(), &counted).expect("run should succeed");
+    assert_eq!(output.contents(), b"1\n");
+}
+
+#[test]
+fn run_pipeline_processes_streamed_lines_before_a_read_error() {
+    /// Yields one line and then fails, like a download cut off part-way.
+    struct Interrupted;
+
+    impl InputSource for Interrupted {
+        fn read_lines(&self, _opts: &ParseOptions) -> anyhow::Result<Vec<String>> {
+            unreachable!("run_pipeline should stream")
+        }
+
+        fn stream_numbered_lines<'a>(
+            &'a self,
+            _opts: &'a ParseOptions,
+        ) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<InputLine>> + 'a>> {
+            let line = InputLine {
+                line_no: Some(1),
+                text: "Alice,30,alice@example.com".into(),
+            };
+            Ok(Box::new(
+                [Ok(line), Err(anyhow::anyhow!("connection reset"))].into_iter(),
+            ))
+        }
+    }
+
+    let mut output = MemorySink::new();
+    let err = run_pipeline(
+        &Interrupted,
+        &mut output,
+        &default_config(),
+        &RunOptions::default(),
+    )
+    .expect_err("the read error should fail the run");
+    assert_eq!(err.to_string(), "connection reset");
+    assert_eq!(output.contents(), b"Alice (30, 30s) -> username=alice\n");
+}
+
+#[test]
+fn v1_config_file_loads_into_current_config() {
+    let v1 = std::fs::read_to_string("tests/data/config_v1.json").expect("fixture exists");
+    let cfg = ValidationConfig::from_json(&v1).expect("v1 config should load");
+    assert_eq!(cfg.config_version, CONFIG_VERSION);
+    assert_eq!(cfg.min_age, 18);
+    assert!(cfg.strict_email);
+    assert!(cfg.require_age);
+    assert_eq!(cfg.duplicate_email_policy, DuplicateEmailPolicy::Allow);
+
+    let tolerant = ValidationConfig::from_json(
+        r#"{"config_version": 2, "min_age": 21, "shiny_new_option": true, "parse": {"lenient_age": true, "typo": 1}}"#,
+    )
+    .expect("unknown keys are ignored");
+    assert_eq!(tolerant.min_age, 21);
+    assert!(tolerant.parse.lenient_age);
+
+    assert!(matches!(
+        ValidationConfig::from_json(r#"{"config_version": 3}"#),
+        Err(PipelineError::InvalidConfig { .. })
+    ));
+}
+
+#[test]
+fn config_and_records_round_trip_through_serde() {
+    let cfg = ValidationConfig::builder()
+        .min_age(21)
+        .strict_email(true)
+        .sort_by(SortKey::Age, true)
+        .label_override("50+", "senior")
+        .build()
+        .expect("valid config");
+    let json = serde_json::to_string(&cfg).expect("config serialises");
+    let loaded = ValidationConfig::from_json(&json).expect("current config loads");
+    assert_eq!(
+        serde_json::to_value(&loaded).unwrap(),
+        serde_json::to_value(&cfg).unwrap()
+    );
+
+    let enriched = process_lines_enriched(
+        vec!["Alice,30,alice@example.com".to_string()],
+        &default_config(),
+    )
+    .expect("processing should succeed")
+    .remove(0);
+    let json = serde_json::to_string(&enriched).expect("record serialises");
+    let back: monadic_pipeline::EnrichedUser = serde_json::from_str(&json).expect("record loads");
+    assert_eq!(back, enriched);
+    let user: monadic_pipeline::User =
+        serde_json::from_value(serde_json::to_value(&enriched.user).unwrap()).unwrap();
+    assert_eq!(user, enriched.user);
+}
+
+#[cfg(feature = "channel")]
+#[test]
+fn channel_worker_processes_lines_in_order() {
+    use monadic_pipeline::channel::spawn_worker;
+    use std::sync::mpsc;
+
+    let (lines, input) = mpsc::channel();
+    let (output, results) = mpsc::sync_channel(1);
+    let worker = spawn_worker(default_config(), input, output);
+
+    for line in [
+        "Alice,30,alice@example.com",
+        "Bob,abc,bob@example.com",
+        "Carol,38,carol@example.com",
+    ] {
+        lines.send(line.to_string()).unwrap();
+    }
+    drop(lines);
+
+    let collected: Vec<_> = results.iter().collect();
+    worker.join().expect("worker exits cleanly");
+    assert_eq!(collected.len(), 3);
+    assert_eq!(
+        collected[0].as_deref(),
+        Ok("Alice (30, 30s) -> username=alice")
+    );
+    assert!(matches!(collected[1], Err(PipelineError::Parse { .. })));
+    assert_eq!(
+        collected[2].as_deref(),
+        Ok("Carol (38, 30s) -> username=carol")
+    );
+}